@@ -2,42 +2,354 @@
 AnalyzeResult {
     file_location: "crate",
     services: [
+        Service {
+            name: "me",
+            location: "crate::api::account::AccountController",
+            owner: Some(
+                "crate::api::account::AccountController",
+            ),
+            receiver: Some(
+                Ref,
+            ),
+            arguments: [],
+            return_type: Reference {
+                name: "Account",
+                args: [],
+            },
+            error_type: None,
+            before: [],
+            after: [],
+            guards: [],
+            cfg: [],
+        },
+        Service {
+            name: "rename",
+            location: "crate::api::account::AccountController",
+            owner: Some(
+                "crate::api::account::AccountController",
+            ),
+            receiver: Some(
+                Ref,
+            ),
+            arguments: [
+                Argument {
+                    name: "display_name",
+                    ty: String,
+                    type_paths: [
+                        "::String",
+                    ],
+                    injected: false,
+                    shared: false,
+                },
+            ],
+            return_type: Reference {
+                name: "Account",
+                args: [],
+            },
+            error_type: Some(
+                Reference {
+                    name: "AccountError",
+                    args: [],
+                },
+            ),
+            before: [],
+            after: [
+                "crate::api::account::audit",
+            ],
+            guards: [],
+            cfg: [],
+        },
+        Service {
+            name: "whoami",
+            location: "crate::api::account",
+            owner: None,
+            receiver: None,
+            arguments: [
+                Argument {
+                    name: "user",
+                    ty: Reference {
+                        name: "User",
+                        args: [],
+                    },
+                    type_paths: [
+                        "crate::user::User",
+                    ],
+                    injected: true,
+                    shared: false,
+                },
+                Argument {
+                    name: "verbose",
+                    ty: Nullable(
+                        Boolean,
+                    ),
+                    type_paths: [],
+                    injected: false,
+                    shared: false,
+                },
+            ],
+            return_type: String,
+            error_type: None,
+            before: [],
+            after: [],
+            guards: [],
+            cfg: [],
+        },
+        Service {
+            name: "remove",
+            location: "crate::api::account",
+            owner: None,
+            receiver: None,
+            arguments: [
+                Argument {
+                    name: "id",
+                    ty: String,
+                    type_paths: [
+                        "::String",
+                    ],
+                    injected: false,
+                    shared: false,
+                },
+            ],
+            return_type: Void,
+            error_type: None,
+            before: [],
+            after: [],
+            guards: [
+                Permission(
+                    "admin",
+                ),
+            ],
+            cfg: [],
+        },
         Service {
             name: "format",
             location: "crate::api::greeting",
+            owner: None,
+            receiver: None,
             arguments: [
-                "message: String",
-                "input: GreetingInput",
+                Argument {
+                    name: "message",
+                    ty: String,
+                    type_paths: [
+                        "::String",
+                    ],
+                    injected: false,
+                    shared: false,
+                },
+                Argument {
+                    name: "input",
+                    ty: Reference {
+                        name: "GreetingInput",
+                        args: [],
+                    },
+                    type_paths: [
+                        "crate::api::greeting::GreetingInput",
+                    ],
+                    injected: false,
+                    shared: false,
+                },
             ],
-            return_type: "Result < Greeting >",
+            return_type: Reference {
+                name: "Greeting",
+                args: [],
+            },
+            error_type: None,
+            before: [],
+            after: [],
+            guards: [],
+            cfg: [],
         },
         Service {
             name: "say_hi",
             location: "crate::api::greeting",
+            owner: None,
+            receiver: None,
+            arguments: [],
+            return_type: Void,
+            error_type: None,
+            before: [],
+            after: [],
+            guards: [],
+            cfg: [],
+        },
+        Service {
+            name: "salute",
+            location: "crate::api::greeting",
+            owner: None,
+            receiver: None,
             arguments: [],
-            return_type: "Result < Void >",
+            return_type: Reference {
+                name: "Salutation",
+                args: [],
+            },
+            error_type: None,
+            before: [],
+            after: [],
+            guards: [],
+            cfg: [],
+        },
+        Service {
+            name: "history",
+            location: "crate::api::greeting",
+            owner: None,
+            receiver: None,
+            arguments: [
+                Argument {
+                    name: "input",
+                    ty: Reference {
+                        name: "GreetingInput",
+                        args: [],
+                    },
+                    type_paths: [
+                        "crate::api::greeting::GreetingInput",
+                    ],
+                    injected: false,
+                    shared: false,
+                },
+                Argument {
+                    name: "cursor",
+                    ty: Nullable(
+                        String,
+                    ),
+                    type_paths: [],
+                    injected: false,
+                    shared: false,
+                },
+            ],
+            return_type: Reference {
+                name: "Page",
+                args: [
+                    Reference {
+                        name: "Greeting",
+                        args: [],
+                    },
+                ],
+            },
+            error_type: None,
+            before: [],
+            after: [],
+            guards: [],
+            cfg: [],
         },
     ],
     messages: [
+        Message {
+            kind: Struct,
+            name: "Account",
+            location: "crate::api::account",
+            code: "export interface Account {\n\tid: string,\n\tdisplay_name: string,\n}\n",
+            types: [
+                String,
+                String,
+            ],
+        },
+        Message {
+            kind: Enum,
+            name: "AccountError",
+            location: "crate::api::account",
+            code: "export type AccountError = \n\t{ kind: \"name_too_short\", min_length: number } |\n\t{ kind: \"name_taken\", name: string };\n",
+            types: [
+                Number,
+                String,
+            ],
+        },
         Message {
             kind: Enum,
             name: "TimeOfDay",
             location: "crate::api::greeting",
             code: "export type TimeOfDay = \n\t\"Morning\" |\n\t\"Afternoon\" |\n\t\"Evening\";\n",
+            types: [],
+        },
+        Message {
+            kind: Struct,
+            name: "UserId",
+            location: "crate::api::greeting",
+            code: "export type UserId = string;\n",
+            types: [
+                String,
+            ],
+        },
+        Message {
+            kind: Enum,
+            name: "Salutation",
+            location: "crate::api::greeting",
+            code: "export type Salutation = \n\t{ kind: \"anonymous\" } |\n\t{ kind: \"named\", user_id: UserId } |\n\t{ kind: \"formal\", title: string, name: string };\n",
+            types: [
+                Reference {
+                    name: "UserId",
+                    args: [],
+                },
+                String,
+                String,
+            ],
         },
         Message {
             kind: Struct,
             name: "Greeting",
             location: "crate::api::greeting",
-            code: "export interface Greeting {\n\tmessage: String,\n\ttime_of_day: Option < TimeOfDay >,\n}\n",
+            code: "export interface Greeting {\n\tmessage: string,\n\ttime_of_day?: TimeOfDay | null,\n\tmetadata?: unknown | null,\n}\n",
+            types: [
+                String,
+                Nullable(
+                    Reference {
+                        name: "TimeOfDay",
+                        args: [],
+                    },
+                ),
+                Nullable(
+                    Mapped {
+                        ts_type: "unknown",
+                        import: None,
+                        args: [],
+                    },
+                ),
+            ],
+        },
+        Message {
+            kind: Struct,
+            name: "Page",
+            location: "crate::api::greeting",
+            code: "export interface Page<T> {\n\titems: T[],\n\ttotal: bigint,\n\tcursor?: string | null,\n}\n",
+            types: [
+                Array(
+                    TypeParam(
+                        "T",
+                    ),
+                ),
+                BigInt,
+                Nullable(
+                    String,
+                ),
+            ],
         },
         Message {
             kind: Struct,
             name: "GreetingInput",
             location: "crate::api::greeting",
-            code: "export interface GreetingInput {\n\tname: String,\n}\n",
+            code: "export interface GreetingInput {\n\tname: string,\n}\n",
+            types: [
+                String,
+            ],
+        },
+    ],
+    injectables: [
+        Injectable {
+            type_path: "crate::user::User",
+            is_async: false,
+            dependencies: [],
+        },
+        Injectable {
+            type_path: "crate::api::account::AccountController",
+            is_async: false,
+            dependencies: [
+                "crate::user::User",
+            ],
         },
     ],
+    permission_checkers: [
+        "crate::user::UserPermissions",
+    ],
+    warnings: [],
 }
 */
 /* custom header */
@@ -45,6 +357,31 @@ export interface Transport {
 	send(fn: string, args: string): Promise<string>;
 }
 
+export class MachineryError<D = unknown> extends Error {
+	code: string;
+	details?: D;
+	service?: string;
+	chain?: string[];
+
+	constructor(error: { message: string, code: string, details?: D, service?: string, chain?: string[] }) {
+		super(error.message);
+		this.name = "MachineryError";
+		this.code = error.code;
+		this.details = error.details;
+		this.service = error.service;
+		this.chain = error.chain;
+	}
+}
+
+// the typed error of a service, `details` holds the serialized error and
+// `service` the service that returned it. Each service with one gets a guard
+// that tells its type, eg. `isAccountAccountControllerRenameError` for
+// `account::AccountController::rename`
+export function isServiceError(error: unknown, service?: string): error is MachineryError {
+	return error instanceof MachineryError && error.code === "service_error"
+		&& (service === undefined || error.service === service);
+}
+
 const deepAssign: typeof Object.assign = (target: any, ...sources: any[]) => {
     for (const source of sources) {
         for (let k in source) {
@@ -60,56 +397,91 @@ const deepAssign: typeof Object.assign = (target: any, ...sources: any[]) => {
 }
 
 
+function reviveBigInts(value: any, paths: (string | number)[][]) {
+	for (const path of paths) {
+		if (path.length === 0) {
+			return BigInt(value);
+		}
+		let target = value;
+		for (const key of path.slice(0, -1)) {
+			target = target[key];
+		}
+		const key = path[path.length - 1];
+		target[key] = BigInt(target[key]);
+	}
+	return value;
+}
+
 function handleResult(result: string) {
 	const json = JSON.parse(result);
 	if (json.error) {
-		throw new Error(json.error);
+		if (json.error.bigints) {
+			json.error.details = reviveBigInts(json.error.details, json.error.bigints);
+		}
+		throw new MachineryError(json.error);
 	}
-	return json.result;
+	return reviveBigInts(json.result, json.bigints ?? []);
 }
 
-export type Option<T> = T | undefined;
-export type Result<T> = T;
-export type Vec<T> = T[];
-
-export type String = string;
-export type Void = void;
-
-export type u8 = number;
-export type u16 = number;
-export type u32 = number;
-export type u64 = number;
-export type usize = number;
-
-export type i8 = number;
-export type i16 = number;
-export type i32 = number;
-export type i64 = number;
-export type isize = number;
+function stringifyArgs(args: any[]) {
+	return JSON.stringify(args, (_, value) => typeof value === "bigint" ? value.toString() : value);
+}
+export type Custom = string;
+export interface Account {
+	id: string,
+	display_name: string,
+}
 
-export type f32 = number;
-export type f64 = number;
+export type AccountError = 
+	{ kind: "name_too_short", min_length: number } |
+	{ kind: "name_taken", name: string };
 
-export type bool = boolean;
-export type Custom = string;
 export type TimeOfDay = 
 	"Morning" |
 	"Afternoon" |
 	"Evening";
 
+export type UserId = string;
+
+export type Salutation = 
+	{ kind: "anonymous" } |
+	{ kind: "named", user_id: UserId } |
+	{ kind: "formal", title: string, name: string };
+
 export interface Greeting {
-	message: String,
-	time_of_day: Option < TimeOfDay >,
+	message: string,
+	time_of_day?: TimeOfDay | null,
+	metadata?: unknown | null,
+}
+
+export interface Page<T> {
+	items: T[],
+	total: bigint,
+	cursor?: string | null,
 }
 
 export interface GreetingInput {
-	name: String,
+	name: string,
 }
 
 export function createClient(transport: Transport) {
 	let obj0 = {};
-	let obj1 = deepAssign(obj0, { greeting: { async format(message: String, input: GreetingInput): Promise<Result < Greeting >> { return handleResult(await transport.send("api::greeting::format", JSON.stringify([message ?? null, input ?? null]))); } }});
-	let obj2 = deepAssign(obj1, { greeting: { async say_hi(): Promise<Result < Void >> { return handleResult(await transport.send("api::greeting::say_hi", JSON.stringify([]))); } }});
-	return obj2;
+	let obj1 = deepAssign(obj0, { account: { AccountController: { async me(): Promise<Account> { return handleResult(await transport.send("api::account::AccountController::me", stringifyArgs([]))); } }}});
+	let obj2 = deepAssign(obj1, { account: { AccountController: { /** @throws {MachineryError<AccountError>} */ async rename(display_name: string): Promise<Account> { return handleResult(await transport.send("api::account::AccountController::rename", stringifyArgs([display_name ?? null]))); } }}});
+	let obj3 = deepAssign(obj2, { account: { async whoami(verbose?: boolean | null): Promise<string> { return handleResult(await transport.send("api::account::whoami", stringifyArgs([verbose ?? null]))); } }});
+	let obj4 = deepAssign(obj3, { account: { /** @permission admin */ async remove(id: string): Promise<void> { return handleResult(await transport.send("api::account::remove", stringifyArgs([id ?? null]))); } }});
+	let obj5 = deepAssign(obj4, { greeting: { async format(message: string, input: GreetingInput): Promise<Greeting> { return handleResult(await transport.send("api::greeting::format", stringifyArgs([message ?? null, input ?? null]))); } }});
+	let obj6 = deepAssign(obj5, { greeting: { async say_hi(): Promise<void> { return handleResult(await transport.send("api::greeting::say_hi", stringifyArgs([]))); } }});
+	let obj7 = deepAssign(obj6, { greeting: { async salute(): Promise<Salutation> { return handleResult(await transport.send("api::greeting::salute", stringifyArgs([]))); } }});
+	let obj8 = deepAssign(obj7, { greeting: { async history(input: GreetingInput, cursor?: string | null): Promise<Page<Greeting>> { return handleResult(await transport.send("api::greeting::history", stringifyArgs([input ?? null, cursor ?? null]))); } }});
+	return obj8;
+};
+
+export const permissions: Record<string, string[]> = {
+	"api::account::remove": ["admin"],
 };
+
+export function isAccountAccountControllerRenameError(error: unknown): error is MachineryError<AccountError> & { details: AccountError } {
+	return isServiceError(error, "api::account::AccountController::rename");
+}
 /* custom_footer */
\ No newline at end of file
@@ -1,4 +1,4 @@
-use machinery_build::pipeline;
+use machinery_build::pipeline::{self, Int64Encoding, TypeMapping};
 
 fn main() {
     // println!("do nothing");
@@ -6,6 +6,8 @@ fn main() {
         .add_files("./**/*.rs")
         .with_base_crate_path("crate::api")
         .export_to_dir("./bindings/")
+        .with_int64_encoding(Int64Encoding::BigInt)
+        .with_type_mapping(TypeMapping::new("json::Value", "unknown"))
         .with_custom_types(vec!["Custom = string"])
         .with_custom_header("/* custom header */")
         .with_custom_footer("/* custom_footer */")
@@ -0,0 +1,92 @@
+use std::sync::Arc;
+
+use machinery::{
+    context::Context, inject, inject::Injectable, intercept::Call, MachineryResponse, Result, Void,
+};
+
+use crate::user::User;
+
+#[machinery::message]
+pub struct Account {
+    id: String,
+    display_name: String,
+}
+
+#[machinery::message]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AccountError {
+    NameTooShort { min_length: u32 },
+    NameTaken { name: String },
+}
+
+pub struct AccountSettings {
+    pub min_name_length: u32,
+}
+
+pub struct AccountController {
+    user: Arc<User>,
+    min_name_length: u32,
+}
+
+#[machinery::injectable]
+impl Injectable for AccountController {
+    fn inject(ctx: &Context) -> Result<Box<AccountController>> {
+        Ok(Box::new(AccountController {
+            user: inject!(User)?,
+            min_name_length: ctx.state::<AccountSettings>()?.min_name_length,
+        }))
+    }
+}
+
+impl AccountController {
+    #[machinery::service]
+    pub async fn me(&self) -> Result<Account> {
+        Account {
+            id: self.user.id.clone(),
+            display_name: format!("User {}", self.user.id),
+        }
+        .into()
+    }
+
+    #[machinery::service(after = audit)]
+    pub async fn rename(&self, display_name: String) -> Result<Account, AccountError> {
+        if display_name.len() < self.min_name_length as usize {
+            return Err(AccountError::NameTooShort {
+                min_length: self.min_name_length,
+            });
+        }
+
+        if display_name == "admin" {
+            return Err(AccountError::NameTaken { name: display_name });
+        }
+
+        Ok(Account {
+            id: self.user.id.clone(),
+            display_name,
+        })
+    }
+}
+
+#[machinery::service]
+pub async fn whoami(#[inject] user: User, verbose: Option<bool>) -> Result<String> {
+    match verbose {
+        Some(true) => Ok(format!("You are signed in as user {}", user.id)),
+        _ => Ok(user.id),
+    }
+}
+
+#[machinery::service(guard = "admin")]
+pub async fn remove(id: String) -> Result<Void> {
+    log::info!("removing account {}", id);
+    Ok(())
+}
+
+pub async fn audit(_ctx: &Context, call: &Call, response: &MachineryResponse) -> Result<()> {
+    log::info!(
+        "{} {} answered {}",
+        call.service,
+        call.args,
+        response.status
+    );
+    Ok(())
+}
@@ -1,4 +1,11 @@
-use machinery::{error::bail, Result, Void};
+use machinery::{
+    error::{bail, ServiceError},
+    inject,
+    json::Value,
+    Result, Void,
+};
+
+use crate::user::User;
 
 #[machinery::message]
 pub enum TimeOfDay {
@@ -7,10 +14,30 @@ pub enum TimeOfDay {
     Evening,
 }
 
+#[machinery::message]
+pub struct UserId(String);
+
+#[machinery::message]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Salutation {
+    Anonymous,
+    Named { user_id: UserId },
+    Formal { title: String, name: String },
+}
+
 #[machinery::message]
 pub struct Greeting {
     message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     time_of_day: Option<TimeOfDay>,
+    metadata: Option<Value>,
+}
+
+#[machinery::message]
+pub struct Page<T> {
+    items: Vec<T>,
+    total: u64,
+    cursor: Option<String>,
 }
 
 #[machinery::message]
@@ -23,12 +50,13 @@ pub async fn format(message: String, input: GreetingInput) -> Result<Greeting> {
     // tokio::time::sleep(tokio::time::Duration::from_secs(3)).await;
 
     if input.name == "Laurentiu 4" {
-        bail!("invalid name");
+        bail!(ServiceError::new("invalid_name", "invalid name"));
     }
 
     Greeting {
         message: format!("{} {}", message, input.name),
         time_of_day: TimeOfDay::Morning.into(),
+        metadata: None,
     }
     .into()
 }
@@ -38,3 +66,29 @@ pub async fn say_hi() -> Result<Void> {
     log::info!("hi from say_hi()");
     Ok(())
 }
+
+#[machinery::service]
+pub async fn salute() -> Result<Salutation> {
+    let Ok(user) = inject!(User) else {
+        return Salutation::Anonymous.into();
+    };
+
+    Salutation::Named {
+        user_id: UserId(user.id.clone()),
+    }
+    .into()
+}
+
+#[machinery::service]
+pub async fn history(input: GreetingInput, cursor: Option<String>) -> Result<Page<Greeting>> {
+    Page {
+        items: vec![Greeting {
+            message: format!("Hello {}", input.name),
+            time_of_day: None,
+            metadata: None,
+        }],
+        total: 1,
+        cursor,
+    }
+    .into()
+}
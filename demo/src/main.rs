@@ -3,7 +3,22 @@ mod user;
 mod api;
 machinery::load_services!();
 
-use machinery::{machinery, MachineryStandaloneConfig, Result};
+use api::account::AccountSettings;
+use machinery::{
+    context::Context,
+    intercept::{Call, Interceptor},
+    machinery, MachineryStandaloneConfig, Result,
+};
+
+struct LogCalls;
+
+#[machinery::interceptor]
+impl Interceptor for LogCalls {
+    async fn before(&self, _ctx: &Context, call: &Call) -> Result<()> {
+        log::info!("calling {}", call.service);
+        Ok(())
+    }
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -13,6 +28,8 @@ async fn main() -> Result<()> {
 
     machinery!()
         .with_standalone_config(MachineryStandaloneConfig { listen_addr })
+        .intercept(LogCalls)
+        .with_state(AccountSettings { min_name_length: 3 })
         .boot()
         .await
 }
@@ -1,4 +1,11 @@
-use machinery::{context::Context, error::bail, inject::Injectable, Result};
+use machinery::{
+    context::Context,
+    error::{bail, ServiceError},
+    guard::Permissions,
+    inject,
+    inject::Injectable,
+    Result,
+};
 
 pub struct User {
     pub id: String,
@@ -8,11 +15,11 @@ pub struct User {
 impl Injectable for User {
     fn inject(ctx: &Context) -> Result<Box<User>> {
         let Some(user_id) = ctx.headers.get("user_id") else {
-            bail!("user_id is not found");
+            bail!(ServiceError::unauthorized("user_id is not found"));
         };
 
         let Ok(user_id) = user_id.to_str() else {
-            bail!("user_id is not found");
+            bail!(ServiceError::unauthorized("user_id is not found"));
         };
 
         Ok(Box::new(User {
@@ -20,3 +27,21 @@ impl Injectable for User {
         }))
     }
 }
+
+pub struct UserPermissions;
+
+#[machinery::guard]
+impl Permissions for UserPermissions {
+    async fn check(ctx: &Context, permission: &str) -> Result<()> {
+        let user = inject!(User)?;
+
+        // the first user is the only admin
+        match permission {
+            "admin" if user.id == "1" => Ok(()),
+            _ => bail!(ServiceError::forbidden(format!(
+                "the {} permission is required",
+                permission
+            ))),
+        }
+    }
+}
@@ -1,4 +1,9 @@
-use syn::{visit::Visit, Attribute};
+use syn::{ext::IdentExt, visit::Visit, Attribute};
+
+use crate::cfg;
+use crate::imports::Imports;
+use crate::serde_attrs::{RenameRule, SerdeAttrs, Tagging};
+use crate::types::{TsType, TypeMapper};
 
 #[derive(Debug)]
 pub enum MessageKind {
@@ -12,21 +17,106 @@ pub struct Message {
     pub name: String,
     pub location: String,
     pub code: String,
+    pub types: Vec<TsType>,
+}
+
+#[derive(Debug)]
+pub struct Argument {
+    pub name: String,
+    pub ty: TsType,
+    // the paths the Rust type may refer to from the crate root, for plain
+    // paths only
+    pub type_paths: Vec<String>,
+    // resolved by the handler instead of being sent by the client
+    pub injected: bool,
+    // an `Arc<T>` gets the injected value of the request, `type_paths` are
+    // the ones of `T`
+    pub shared: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReceiverKind {
+    Ref,
+    RefMut,
+    Value,
+}
+
+#[derive(Debug)]
+pub enum ServiceGuard {
+    // `guard = "admin"`, checked by the crate's `Permissions`
+    Permission(String),
+    // `guard = AdminOnly`, the path of a type implementing `Guard`, from
+    // the crate root or into another crate
+    Type(String),
 }
 
 #[derive(Debug)]
 pub struct Service {
     pub name: String,
     pub location: String,
-    pub arguments: Vec<String>,
-    pub return_type: String,
+    // the type of the impl block for services that are methods
+    pub owner: Option<String>,
+    pub receiver: Option<ReceiverKind>,
+    pub arguments: Vec<Argument>,
+    pub return_type: TsType,
+    // `E` of a `Result<T, E>` with a named error type
+    pub error_type: Option<TsType>,
+    // the interceptors of `#[service(before = .., after = ..)]`, as paths
+    // from the crate root or `::` paths into other crates
+    pub before: Vec<String>,
+    pub after: Vec<String>,
+    pub guards: Vec<ServiceGuard>,
+    // the `#[cfg]` predicates of the service and the items around it that
+    // only rustc can decide, its handler is compiled under the same ones
+    pub cfg: Vec<String>,
+}
+
+// the arguments of `#[service(..)]`
+#[derive(Default)]
+struct ServiceOptions {
+    before: Vec<String>,
+    after: Vec<String>,
+    guards: Vec<ServiceGuard>,
+}
+
+impl Service {
+    /// The path the generated handler calls the service through.
+    pub fn rust_path(&self) -> String {
+        format!(
+            "{}::{}",
+            self.owner.as_ref().unwrap_or(&self.location),
+            self.name
+        )
+    }
+
+    pub fn permissions(&self) -> impl Iterator<Item = &String> {
+        self.guards.iter().filter_map(|guard| match guard {
+            ServiceGuard::Permission(permission) => Some(permission),
+            ServiceGuard::Type(_) => None,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Injectable {
+    pub type_path: String,
+    pub is_async: bool,
+    // the types its implementation asks for with `inject!` and `inject_async!`
+    pub dependencies: Vec<String>,
 }
 
 #[derive(Debug)]
-pub struct MachineryVisitor {
-    file_location: String,
+pub struct MachineryVisitor<'a> {
+    mapper: &'a TypeMapper,
+    module_path: Vec<String>,
+    imports: Imports,
+    // the undecided `#[cfg]` predicates of the current module
+    cfg: Vec<String>,
     services: Vec<Service>,
     messages: Vec<Message>,
+    injectables: Vec<Injectable>,
+    permission_checkers: Vec<String>,
+    warnings: Vec<String>,
 }
 
 #[derive(Debug)]
@@ -34,180 +124,1137 @@ pub struct AnalyzeResult {
     pub file_location: String,
     pub services: Vec<Service>,
     pub messages: Vec<Message>,
+    pub injectables: Vec<Injectable>,
+    // types implementing `guard::Permissions`
+    pub permission_checkers: Vec<String>,
+    // what was left out of the analysis, reported as cargo warnings
+    pub warnings: Vec<String>,
 }
 
-fn has_machinery_attribute(attrs: Vec<Attribute>, name: &str) -> bool {
-    let full_name = format!("machinery::{}", name);
+// the attribute macros are exported by both crates
+const MACHINERY_CRATES: [&str; 2] = ["machinery", "machinery_meta"];
 
-    for attr in attrs {
-        let path = attr
-            .path()
-            .segments
-            .iter()
-            .map(|s| s.ident.to_string())
-            .collect::<Vec<_>>()
-            .join("::");
+fn is_machinery_path(imports: &Imports, path: &syn::Path, name: &[&str]) -> bool {
+    imports.resolve(path).iter().any(|path| {
+        path.len() == name.len() + 1
+            && MACHINERY_CRATES.contains(&path[0].as_str())
+            && path[1..].iter().zip(name).all(|(a, b)| a == b)
+    })
+}
 
-        if path == full_name {
-            return true;
+fn has_machinery_attribute(imports: &Imports, attrs: &[Attribute], name: &str) -> bool {
+    attrs
+        .iter()
+        .any(|attr| is_machinery_path(imports, attr.path(), &[name]))
+}
+
+// collects the types injected with `inject!` and `inject_async!`
+struct InjectCalls<'v, 'a> {
+    visitor: &'v MachineryVisitor<'a>,
+    dependencies: Vec<String>,
+}
+
+impl<'ast> Visit<'ast> for InjectCalls<'_, '_> {
+    fn visit_macro(&mut self, i: &'ast syn::Macro) {
+        let imports = &self.visitor.imports;
+        if !is_machinery_path(imports, &i.path, &["inject"])
+            && !is_machinery_path(imports, &i.path, &["inject_async"])
+        {
+            return;
+        }
+
+        // all the candidates of a glob import, the ones that aren't
+        // injectables are left out of the dependency graph anyway
+        if let Ok(path) = i.parse_body::<syn::Path>() {
+            self.dependencies.extend(self.visitor.absolute_paths(&path));
         }
     }
+}
 
-    false
+// maps the types used by a single message and remembers them, so the
+// pipeline can report the ones that don't resolve to anything
+struct TypeScope<'a> {
+    mapper: &'a TypeMapper,
+    imports: &'a Imports,
+    type_params: Vec<String>,
+    types: Vec<TsType>,
 }
 
-fn parse_enum(item: &syn::ItemEnum) -> String {
-    let mut output = String::new();
+impl<'a> TypeScope<'a> {
+    fn new(mapper: &'a TypeMapper, imports: &'a Imports, generics: &syn::Generics) -> Self {
+        TypeScope {
+            mapper,
+            imports,
+            type_params: generics
+                .type_params()
+                .map(|param| param.ident.to_string())
+                .collect(),
+            types: Vec::new(),
+        }
+    }
 
-    output.push_str(&format!("export type {} = \n", item.ident.to_string())[..]);
+    fn map(&mut self, ty: &syn::Type) -> TsType {
+        let ty = self.mapper.map(ty, &self.type_params, self.imports);
+        self.types.push(ty.clone());
 
-    for variant in item.variants.iter() {
-        let name = variant.ident.to_string();
+        ty
+    }
 
-        output.push_str(&format!("\t\"{}\"", name));
+    fn parse_type(&mut self, ty: &syn::Type) -> String {
+        self.map(ty).to_string()
+    }
+}
 
-        if name == item.variants.last().unwrap().ident.to_string() {
-            output.push_str(";\n");
-        } else {
-            output.push_str(" |\n");
+// Rust bounds have no TS counterpart so only the parameter names and their
+// defaults are kept; lifetimes and const parameters never reach the wire
+fn parse_generics(generics: &syn::Generics, scope: &mut TypeScope) -> Vec<String> {
+    generics
+        .type_params()
+        .map(|param| match &param.default {
+            Some(default) => format!("{} = {}", param.ident, scope.parse_type(default)),
+            None => param.ident.to_string(),
+        })
+        .collect()
+}
+
+fn ts_generics(generics: &[String]) -> String {
+    if generics.is_empty() {
+        return "".to_owned();
+    }
+
+    format!("<{}>", generics.join(", "))
+}
+
+fn ts_property_name(name: &str) -> String {
+    let mut chars = name.chars();
+    let is_identifier = chars
+        .next()
+        .map(|ch| ch.is_ascii_alphabetic() || ch == '_' || ch == '$')
+        .unwrap_or(false)
+        && chars.all(|ch| ch.is_ascii_alphanumeric() || ch == '_' || ch == '$');
+
+    if is_identifier {
+        name.to_owned()
+    } else {
+        format!("{:?}", name)
+    }
+}
+
+fn ts_object(members: &[String], flattened: &[String]) -> String {
+    let object = format!("{{ {} }}", members.join(", "));
+
+    if flattened.is_empty() {
+        return object;
+    }
+
+    std::iter::once(object)
+        .chain(flattened.iter().cloned())
+        .collect::<Vec<_>>()
+        .join(" & ")
+}
+
+// returns the object members and the types of #[serde(flatten)] fields, which
+// become intersections with the object
+fn parse_named_fields(
+    fields: &syn::FieldsNamed,
+    rename_all: Option<RenameRule>,
+    all_optional: bool,
+    scope: &mut TypeScope,
+) -> (Vec<String>, Vec<String>) {
+    let mut members = Vec::new();
+    let mut flattened = Vec::new();
+
+    for field in fields.named.iter() {
+        let serde_attrs = SerdeAttrs::from_attrs(&field.attrs);
+
+        if serde_attrs.skip {
+            continue;
         }
+
+        let ty = scope.map(&field.ty);
+
+        // a flattened Option<T> contributes either all of T's fields or none
+        if serde_attrs.flatten {
+            match ty {
+                TsType::Nullable(inner) => flattened.push(format!("Partial<{}>", inner)),
+                ty => flattened.push(ty.to_string()),
+            }
+            continue;
+        }
+
+        let name = field.ident.as_ref().unwrap().unraw().to_string();
+        let name = match (serde_attrs.rename, rename_all) {
+            (Some(rename), _) => rename,
+            (None, Some(rule)) => rule.apply_to_field(&name),
+            (None, None) => name,
+        };
+
+        // serde treats a missing Option field as None
+        let nullable = matches!(ty, TsType::Nullable(_));
+        let optional = if serde_attrs.optional || all_optional || nullable {
+            "?"
+        } else {
+            ""
+        };
+
+        members.push(format!("{}{}: {}", ts_property_name(&name), optional, ty));
+    }
+
+    (members, flattened)
+}
+
+fn parse_fields_as_tuple(fields: &syn::FieldsUnnamed, scope: &mut TypeScope) -> String {
+    let fields = fields
+        .unnamed
+        .iter()
+        .filter(|field| !SerdeAttrs::from_attrs(&field.attrs).skip)
+        .map(|field| scope.parse_type(&field.ty))
+        .collect::<Vec<_>>();
+
+    format!("[{}]", fields.join(", "))
+}
+
+// serde's default (externally tagged) representation:
+//   Unit        => "Unit"
+//   Newtype(T)  => { "Newtype": T }
+//   Tuple(A, B) => { "Tuple": [A, B] }
+//   Struct { a } => { "Struct": { a: A } }
+// #[serde(tag)], #[serde(tag, content)] and #[serde(untagged)] reshape these
+// the same way serde does
+fn parse_enum_variant(
+    variant: &syn::Variant,
+    container: &SerdeAttrs,
+    scope: &mut TypeScope,
+) -> Option<String> {
+    let serde_attrs = SerdeAttrs::from_attrs(&variant.attrs);
+
+    if serde_attrs.skip {
+        return None;
     }
 
-    if item.variants.is_empty() {
+    let name = variant.ident.unraw().to_string();
+    let name = match (serde_attrs.rename.clone(), container.rename_all) {
+        (Some(rename), _) => rename,
+        (None, Some(rule)) => rule.apply_to_variant(&name),
+        (None, None) => name,
+    };
+    let name = format!("{:?}", name);
+
+    let tagging = if serde_attrs.untagged {
+        Tagging::Untagged
+    } else {
+        container.tagging()
+    };
+
+    let fields_rename_all = serde_attrs.rename_all.or(container.rename_all_fields);
+
+    let variant = match (&variant.fields, tagging) {
+        (syn::Fields::Unit, Tagging::External) => name,
+        (syn::Fields::Unit, Tagging::Untagged) => "null".to_owned(),
+        (syn::Fields::Unit, Tagging::Internal { tag } | Tagging::Adjacent { tag, .. }) => {
+            format!("{{ {}: {} }}", ts_property_name(&tag), name)
+        }
+        (syn::Fields::Named(fields), Tagging::Internal { tag }) => {
+            let (mut members, flattened) =
+                parse_named_fields(fields, fields_rename_all, false, scope);
+            members.insert(0, format!("{}: {}", ts_property_name(&tag), name));
+
+            ts_object(&members, &flattened)
+        }
+        // serde_derive only accepts newtype variants here and reports the
+        // others, which can't be sent at all
+        (syn::Fields::Unnamed(fields), Tagging::Internal { tag }) => match fields.unnamed.len() {
+            1 => {
+                let ty = scope.parse_type(&fields.unnamed[0].ty);
+
+                format!("{{ {}: {} }} & {}", ts_property_name(&tag), name, ty)
+            }
+            _ => "never".to_owned(),
+        },
+        (fields, tagging) => {
+            let payload = match fields {
+                syn::Fields::Named(fields) => {
+                    let (members, flattened) =
+                        parse_named_fields(fields, fields_rename_all, false, scope);
+                    ts_object(&members, &flattened)
+                }
+                syn::Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                    scope.parse_type(&fields.unnamed.first().unwrap().ty)
+                }
+                syn::Fields::Unnamed(fields) => parse_fields_as_tuple(fields, scope),
+                syn::Fields::Unit => unreachable!(),
+            };
+
+            match tagging {
+                Tagging::Adjacent { tag, content } => format!(
+                    "{{ {}: {}, {}: {} }}",
+                    ts_property_name(&tag),
+                    name,
+                    ts_property_name(&content),
+                    payload
+                ),
+                Tagging::Untagged => payload,
+                _ => format!("{{ {}: {} }}", name, payload),
+            }
+        }
+    };
+
+    Some(variant)
+}
+
+fn parse_enum(item: &syn::ItemEnum, generics: &[String], scope: &mut TypeScope) -> String {
+    let mut output = String::new();
+    let serde_attrs = SerdeAttrs::from_attrs(&item.attrs);
+
+    output.push_str(&format!(
+        "export type {}{} = \n",
+        item.ident,
+        ts_generics(generics)
+    ));
+
+    let variants = item
+        .variants
+        .iter()
+        .filter_map(|variant| parse_enum_variant(variant, &serde_attrs, scope))
+        .map(|variant| format!("\t{}", variant))
+        .collect::<Vec<_>>();
+
+    if variants.is_empty() {
         output.push_str("never;\n");
+    } else {
+        output.push_str(&variants.join(" |\n"));
+        output.push_str(";\n");
     }
 
     output
 }
 
-fn parse_struct(item: &syn::ItemStruct) -> String {
+// newtypes and #[serde(transparent)] structs serialize as their only field,
+// tuple structs as arrays and unit structs as null. Like serde, a newtype is
+// a struct written with one field, skipped fields only leave tuples
+fn parse_struct_alias(
+    item: &syn::ItemStruct,
+    serde_attrs: &SerdeAttrs,
+    scope: &mut TypeScope,
+) -> Option<String> {
+    let fields = item
+        .fields
+        .iter()
+        .filter(|field| !SerdeAttrs::from_attrs(&field.attrs).skip)
+        .collect::<Vec<_>>();
+
+    match &item.fields {
+        _ if serde_attrs.transparent && fields.len() == 1 => Some(scope.parse_type(&fields[0].ty)),
+        syn::Fields::Unit => Some("null".to_owned()),
+        syn::Fields::Unnamed(unnamed) if unnamed.unnamed.len() == 1 => {
+            Some(scope.parse_type(&unnamed.unnamed[0].ty))
+        }
+        syn::Fields::Unnamed(unnamed) => Some(parse_fields_as_tuple(unnamed, scope)),
+        syn::Fields::Named(_) => None,
+    }
+}
+
+fn parse_struct(item: &syn::ItemStruct, generics: &[String], scope: &mut TypeScope) -> String {
     let mut output = String::new();
+    let serde_attrs = SerdeAttrs::from_attrs(&item.attrs);
+    let generics = ts_generics(generics);
 
-    output.push_str(&format!("export interface {} {{\n", item.ident.to_string()));
+    if let Some(alias) = parse_struct_alias(item, &serde_attrs, scope) {
+        output.push_str(&format!(
+            "export type {}{} = {};\n",
+            item.ident, generics, alias
+        ));
 
-    for member in item.fields.iter() {
-        if member.ident.is_none() {
-            continue;
+        return output;
+    }
+
+    let (mut members, flattened) = match &item.fields {
+        syn::Fields::Named(fields) => {
+            parse_named_fields(fields, serde_attrs.rename_all, serde_attrs.optional, scope)
         }
-        let name = member.ident.clone().unwrap().to_string();
+        _ => (Vec::new(), Vec::new()),
+    };
 
-        let ty = &member.ty;
-        let ty = quote::quote!(#ty).to_string();
+    // `#[serde(tag = "type")]` adds the name of the struct to its fields
+    if let Tagging::Internal { tag } = serde_attrs.tagging() {
+        let name = serde_attrs
+            .rename
+            .clone()
+            .unwrap_or_else(|| item.ident.unraw().to_string());
+        members.insert(0, format!("{}: {:?}", ts_property_name(&tag), name));
+    }
+
+    // interfaces can't extend arbitrary types, so flattened fields turn the
+    // message into an intersection type
+    if flattened.is_empty() {
+        output.push_str(&format!("export interface {}{} {{\n", item.ident, generics));
+    } else {
+        output.push_str(&format!("export type {}{} = {{\n", item.ident, generics));
+    }
 
-        output.push_str(&format!("\t{}: {},\n", name, ty));
+    for member in members {
+        output.push_str(&format!("\t{},\n", member));
     }
 
-    output.push_str("}\n");
+    if flattened.is_empty() {
+        output.push_str("}\n");
+    } else {
+        output.push_str(&format!("}} & {};\n", flattened.join(" & ")));
+    }
 
     output
 }
 
-fn parse_fn_arguments(item: &syn::ItemFn) -> Vec<String> {
-    let mut arguments = Vec::new();
+// the service result is unwrapped by the client, and a unit result has no
+// meaningful value on the TS side
+fn parse_fn_return_type(sig: &syn::Signature, mapper: &TypeMapper, imports: &Imports) -> TsType {
+    let ty = match sig.output {
+        syn::ReturnType::Default => return TsType::Void,
+        syn::ReturnType::Type(_, ref ty) => mapper.map(ty, &[], imports),
+    };
+
+    match ty {
+        TsType::Result(ok, _) if *ok == TsType::Null => TsType::Void,
+        TsType::Result(ok, _) => *ok,
+        TsType::Null => TsType::Void,
+        ty => ty,
+    }
+}
+
+// a named error type may be a message that is sent to the client as is
+fn parse_fn_error_type(
+    sig: &syn::Signature,
+    mapper: &TypeMapper,
+    imports: &Imports,
+) -> Option<TsType> {
+    let syn::ReturnType::Type(_, ty) = &sig.output else {
+        return None;
+    };
+
+    match mapper.map(ty, &[], imports) {
+        TsType::Result(_, err) if matches!(*err, TsType::Reference { .. }) => Some(*err),
+        _ => None,
+    }
+}
+
+// the `T` of `Arc<T>`
+fn arc_inner(ty: &syn::Type) -> Option<&syn::Type> {
+    let syn::Type::Path(ty) = ty else {
+        return None;
+    };
+    let segment = ty.path.segments.last()?;
+    let syn::PathArguments::AngleBracketed(arguments) = &segment.arguments else {
+        return None;
+    };
+
+    match arguments.args.first() {
+        Some(syn::GenericArgument::Type(inner))
+            if segment.ident == "Arc" && arguments.args.len() == 1 =>
+        {
+            Some(inner)
+        }
+        _ => None,
+    }
+}
+
+impl MachineryVisitor<'_> {
+    fn parse_fn_arguments(&self, sig: &syn::Signature) -> Vec<Argument> {
+        let mut arguments = Vec::new();
+
+        for (index, input) in sig.inputs.iter().enumerate() {
+            match input {
+                syn::FnArg::Typed(pat) => {
+                    let name = match pat.pat.as_ref() {
+                        syn::Pat::Ident(ident) => ident.ident.unraw().to_string(),
+                        _ => format!("arg{}", index),
+                    };
+                    let shared = arc_inner(&pat.ty);
+                    let type_paths = match shared.unwrap_or(&pat.ty) {
+                        syn::Type::Path(ty) if ty.qself.is_none() => self.absolute_paths(&ty.path),
+                        _ => Vec::new(),
+                    };
+                    let injected = pat.attrs.iter().any(|attr| {
+                        attr.path().is_ident("inject")
+                            || is_machinery_path(&self.imports, attr.path(), &["inject"])
+                    });
+
+                    arguments.push(Argument {
+                        name,
+                        ty: self.mapper.map(&pat.ty, &[], &self.imports),
+                        type_paths,
+                        injected,
+                        shared: shared.is_some(),
+                    });
+                }
+                _ => continue,
+            }
+        }
+
+        arguments
+    }
+
+    fn parse_service_options(&self, attrs: &[Attribute]) -> ServiceOptions {
+        let mut options = ServiceOptions::default();
+
+        for attr in attrs {
+            if !is_machinery_path(&self.imports, attr.path(), &["service"])
+                || !matches!(attr.meta, syn::Meta::List(_))
+            {
+                continue;
+            }
+
+            // the macro reports malformed arguments
+            let _ = attr.parse_nested_meta(|meta| {
+                let value = meta.value()?;
+                if meta.path.is_ident("guard") && value.peek(syn::LitStr) {
+                    let permission = value.parse::<syn::LitStr>()?.value();
+                    options.guards.push(ServiceGuard::Permission(permission));
+                    return Ok(());
+                }
 
-    for input in item.sig.inputs.iter() {
-        match input {
-            syn::FnArg::Typed(pat) => {
-                let ident = pat.pat.as_ref();
-                let name = quote::quote!(#ident).to_string();
+                // `#[machinery::service]` rejects the paths with generic
+                // arguments that can't be resolved
+                let path = value.parse::<syn::Path>()?;
+                let Some(path) = self.absolute_path(&path) else {
+                    return Ok(());
+                };
+                if meta.path.is_ident("before") {
+                    options.before.push(path);
+                } else if meta.path.is_ident("after") {
+                    options.after.push(path);
+                } else if meta.path.is_ident("guard") {
+                    options.guards.push(ServiceGuard::Type(path));
+                }
+                Ok(())
+            });
+        }
 
-                let ty = &pat.ty;
-                let ty = quote::quote!(#ty).to_string();
+        options
+    }
 
-                arguments.push(format!("{}: {}", name, ty));
+    // the handlers can only call methods of a concrete type's own impl
+    fn warn_ignored_services(&mut self, i: &syn::ItemImpl) {
+        for item in &i.items {
+            let syn::ImplItem::Fn(item) = item else {
+                continue;
+            };
+            if has_machinery_attribute(&self.imports, &item.attrs, "service") {
+                self.warnings.push(format!(
+                    "service {}::{} is ignored, services can't be methods of generic impls or trait impls",
+                    self.module_path.join("::"),
+                    item.sig.ident
+                ));
             }
-            _ => continue,
         }
     }
 
-    arguments
-}
+    // the path of a type or function as seen from the crate root, `None` for
+    // paths with generic arguments. Paths into other crates keep a leading
+    // `::`, except for single names which can only come from a glob import
+    // when the module doesn't declare them.
+    fn absolute_path(&self, path: &syn::Path) -> Option<String> {
+        let mut paths = self.absolute_paths(path).into_iter();
+        let direct = paths.next()?;
+
+        if path.segments.len() == 1 && direct.starts_with("::") {
+            if let Some(glob) = paths.find(|path| !path.starts_with("::")) {
+                return Some(glob);
+            }
+        }
+        Some(direct)
+    }
+
+    // every path `path` may refer to through the imports, the glob imports
+    // come after the one it names directly
+    fn absolute_paths(&self, path: &syn::Path) -> Vec<String> {
+        if path
+            .segments
+            .iter()
+            .any(|segment| !segment.arguments.is_empty())
+        {
+            return Vec::new();
+        }
+
+        self.imports
+            .resolve(path)
+            .into_iter()
+            .map(|segments| match path.leading_colon {
+                Some(_) => format!("::{}", segments.join("::")),
+                None => self.rooted_path(&segments),
+            })
+            .collect()
+    }
 
-fn parse_fn_return_type(item: &syn::ItemFn) -> String {
-    match item.sig.output {
-        syn::ReturnType::Default => return "void".to_string(),
-        syn::ReturnType::Type(_, ref ty) => {
-            let ty = &ty;
-            let ty = quote::quote!(#ty).to_string();
+    // only `crate`, `self`, `super` and the items of the current module lead
+    // to local paths, anything else names another crate
+    fn rooted_path(&self, segments: &[String]) -> String {
+        let mut module = self
+            .module_path
+            .join("::")
+            .split("::")
+            .map(|segment| segment.to_owned())
+            .collect::<Vec<_>>();
 
-            ty
+        let mut rest = segments;
+        match rest[0].as_str() {
+            "crate" => return segments.join("::"),
+            "self" => rest = &rest[1..],
+            "super" => {
+                while rest.first().is_some_and(|segment| segment == "super") {
+                    module.pop();
+                    rest = &rest[1..];
+                }
+            }
+            name if self.imports.is_local(name) => {}
+            _ => return format!("::{}", segments.join("::")),
         }
+
+        module.extend(rest.iter().cloned());
+        module.join("::")
     }
 }
 
-impl<'ast> Visit<'ast> for MachineryVisitor {
+impl<'ast, 'a> Visit<'ast> for MachineryVisitor<'a> {
+    // files of `mod foo;` declarations are analyzed on their own, only inline
+    // modules are part of this file
+    fn visit_item_mod(&mut self, i: &'ast syn::ItemMod) {
+        if i.content.is_none() {
+            return;
+        }
+
+        // inline modules don't see the imports of their parent
+        let (_, items) = i.content.as_ref().unwrap();
+        let imports = std::mem::replace(&mut self.imports, Imports::from_items(items));
+
+        let cfg_len = self.cfg.len();
+        self.cfg.extend(cfg::undecided(&i.attrs));
+        self.module_path.push(i.ident.unraw().to_string());
+        syn::visit::visit_item_mod(self, i);
+        self.module_path.pop();
+        self.cfg.truncate(cfg_len);
+
+        self.imports = imports;
+    }
+
     fn visit_item_fn(&mut self, i: &'ast syn::ItemFn) {
-        if !has_machinery_attribute(i.attrs.clone(), "service") {
+        if !has_machinery_attribute(&self.imports, &i.attrs, "service") {
             return;
         }
 
         let name = i.sig.ident.to_string();
 
-        let arguments = parse_fn_arguments(i);
-        let return_type = parse_fn_return_type(i);
+        let arguments = self.parse_fn_arguments(&i.sig);
+        let return_type = parse_fn_return_type(&i.sig, self.mapper, &self.imports);
+        let error_type = parse_fn_error_type(&i.sig, self.mapper, &self.imports);
+        let options = self.parse_service_options(&i.attrs);
 
         self.services.push(Service {
             name,
-            location: self.file_location.clone(),
+            location: self.module_path.join("::"),
+            owner: None,
+            receiver: None,
             arguments,
             return_type,
+            error_type,
+            before: options.before,
+            after: options.after,
+            guards: options.guards,
+            cfg: self
+                .cfg
+                .iter()
+                .cloned()
+                .chain(cfg::undecided(&i.attrs))
+                .collect(),
         });
     }
 
+    // inherent impls hold the services of a controller, trait impls tell which
+    // types can be injected as their receiver and which one checks permissions
+    fn visit_item_impl(&mut self, i: &'ast syn::ItemImpl) {
+        let self_ty = match i.self_ty.as_ref() {
+            syn::Type::Path(self_ty) if self_ty.qself.is_none() && i.generics.params.is_empty() => {
+                Some(self_ty)
+            }
+            _ => None,
+        };
+        let type_path = self_ty.and_then(|self_ty| self.absolute_path(&self_ty.path));
+
+        if i.trait_.is_some() || type_path.is_none() {
+            self.warn_ignored_services(i);
+        }
+        let (Some(self_ty), Some(type_path)) = (self_ty, type_path) else {
+            return;
+        };
+
+        if let Some((_, trait_path, _)) = &i.trait_ {
+            for (name, is_async) in [("Injectable", false), ("AsyncInjectable", true)] {
+                if is_machinery_path(&self.imports, trait_path, &["inject", name]) {
+                    let mut calls = InjectCalls {
+                        visitor: self,
+                        dependencies: Vec::new(),
+                    };
+                    calls.visit_item_impl(i);
+
+                    self.injectables.push(Injectable {
+                        type_path: type_path.clone(),
+                        is_async,
+                        dependencies: calls.dependencies,
+                    });
+                }
+            }
+            if is_machinery_path(&self.imports, trait_path, &["guard", "Permissions"]) {
+                self.permission_checkers.push(type_path);
+            }
+            return;
+        }
+
+        let type_name = self_ty.path.segments.last().unwrap().ident.unraw();
+
+        for item in &i.items {
+            let syn::ImplItem::Fn(item) = item else {
+                continue;
+            };
+            if !has_machinery_attribute(&self.imports, &item.attrs, "service") {
+                continue;
+            }
+
+            let receiver = match item.sig.inputs.first() {
+                Some(syn::FnArg::Receiver(receiver)) => match receiver.reference {
+                    None => Some(ReceiverKind::Value),
+                    Some(_) if receiver.mutability.is_some() => Some(ReceiverKind::RefMut),
+                    Some(_) => Some(ReceiverKind::Ref),
+                },
+                _ => None,
+            };
+            let options = self.parse_service_options(&item.attrs);
+
+            self.services.push(Service {
+                name: item.sig.ident.to_string(),
+                location: format!("{}::{}", self.module_path.join("::"), type_name),
+                owner: Some(type_path.clone()),
+                receiver,
+                arguments: self.parse_fn_arguments(&item.sig),
+                return_type: parse_fn_return_type(&item.sig, self.mapper, &self.imports),
+                error_type: parse_fn_error_type(&item.sig, self.mapper, &self.imports),
+                before: options.before,
+                after: options.after,
+                guards: options.guards,
+                cfg: self
+                    .cfg
+                    .iter()
+                    .cloned()
+                    .chain(cfg::undecided(&i.attrs))
+                    .chain(cfg::undecided(&item.attrs))
+                    .collect(),
+            });
+        }
+    }
+
     fn visit_item_struct(&mut self, i: &'ast syn::ItemStruct) {
-        if !has_machinery_attribute(i.attrs.clone(), "message") {
+        if !has_machinery_attribute(&self.imports, &i.attrs, "message") {
             return;
         }
 
         let name = i.ident.to_string();
+        let mut scope = TypeScope::new(self.mapper, &self.imports, &i.generics);
+        let generics = parse_generics(&i.generics, &mut scope);
+        let code = parse_struct(i, &generics, &mut scope);
 
         self.messages.push(Message {
             kind: MessageKind::Struct,
             name,
-            location: self.file_location.clone(),
-            code: parse_struct(i),
+            location: self.module_path.join("::"),
+            code,
+            types: scope.types,
         });
     }
 
     fn visit_item_enum(&mut self, i: &'ast syn::ItemEnum) {
-        if !has_machinery_attribute(i.attrs.clone(), "message") {
+        if !has_machinery_attribute(&self.imports, &i.attrs, "message") {
             return;
         }
 
         let name = i.ident.to_string();
+        let mut scope = TypeScope::new(self.mapper, &self.imports, &i.generics);
+        let generics = parse_generics(&i.generics, &mut scope);
+        let code = parse_enum(i, &generics, &mut scope);
 
         self.messages.push(Message {
             kind: MessageKind::Enum,
             name,
-            location: self.file_location.clone(),
-            code: parse_enum(i),
+            location: self.module_path.join("::"),
+            code,
+            types: scope.types,
         });
     }
 }
 
-pub fn analyze_file(file: &syn::File, path: &str) -> AnalyzeResult {
-    let file_location = path
-        .to_owned()
-        .split("/")
-        .filter(|s| s.to_owned() != ".")
-        .map(|s| match s {
-            "src" => "crate".to_owned(),
-            _ => s.replace(".rs", ""),
-        })
-        .collect::<Vec<_>>()
-        .join("::");
-
+pub fn analyze_file(
+    file: &syn::File,
+    module_path: &str,
+    cfg: &[String],
+    mapper: &TypeMapper,
+) -> AnalyzeResult {
     let mut visitor = MachineryVisitor {
-        file_location,
+        mapper,
+        module_path: vec![module_path.to_owned()],
+        imports: Imports::from_items(&file.items),
+        cfg: cfg.to_vec(),
         services: Vec::new(),
         messages: Vec::new(),
+        injectables: Vec::new(),
+        permission_checkers: Vec::new(),
+        warnings: Vec::new(),
     };
 
     visitor.visit_file(file);
 
     AnalyzeResult {
-        file_location: visitor.file_location,
+        file_location: module_path.to_owned(),
         services: visitor.services,
         messages: visitor.messages,
+        injectables: visitor.injectables,
+        permission_checkers: visitor.permission_checkers,
+        warnings: visitor.warnings,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alias(item: syn::ItemStruct) -> Option<String> {
+        let mapper = TypeMapper::default();
+        let imports = Imports::default();
+        let mut scope = TypeScope::new(&mapper, &imports, &item.generics);
+
+        parse_struct_alias(&item, &SerdeAttrs::from_attrs(&item.attrs), &mut scope)
+    }
+
+    fn analyze(file: syn::File) -> AnalyzeResult {
+        analyze_file(&file, "crate::api", &[], &TypeMapper::default())
+    }
+
+    #[test]
+    fn finds_machinery_attributes_through_the_imports() {
+        let file: syn::File = syn::parse_quote! {
+            use machinery::service as svc;
+            use machinery_meta::*;
+            use other::message as other_message;
+        };
+        let imports = Imports::from_items(&file.items);
+        let is_service =
+            |attr: syn::Attribute| has_machinery_attribute(&imports, &[attr], "service");
+        let is_message =
+            |attr: syn::Attribute| has_machinery_attribute(&imports, &[attr], "message");
+
+        assert!(is_service(syn::parse_quote!(#[machinery::service])));
+        assert!(is_service(
+            syn::parse_quote!(#[::machinery::service(guard = "admin")])
+        ));
+        assert!(is_service(syn::parse_quote!(#[svc])));
+        assert!(is_service(syn::parse_quote!(#[service])));
+        assert!(is_message(syn::parse_quote!(#[message])));
+        assert!(!is_message(syn::parse_quote!(#[other_message])));
+        assert!(!is_service(syn::parse_quote!(#[machinery::message])));
+        assert!(!is_service(syn::parse_quote!(#[other::service])));
+    }
+
+    #[test]
+    fn finds_machinery_attributes_behind_cfg_attr() {
+        let mut file = syn::parse_quote! {
+            #[cfg_attr(not(windows), machinery::message)]
+            struct Enabled {}
+
+            #[cfg_attr(windows, machinery::message)]
+            struct Disabled {}
+        };
+        crate::cfg::CfgEnv::default().strip(&mut file);
+
+        let result = analyze(file);
+        let names = result
+            .messages
+            .iter()
+            .map(|message| message.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["Enabled"]);
+    }
+
+    #[test]
+    fn warns_about_services_it_cannot_call() {
+        let result = analyze(syn::parse_quote! {
+            impl<T> Ctl<T> {
+                #[machinery::service]
+                async fn generic(&self) {}
+            }
+
+            impl Ctl<u32> {
+                #[machinery::service]
+                async fn concrete(&self) {}
+            }
+
+            impl Api for Ctl {
+                #[machinery::service]
+                async fn from_trait(&self) {}
+
+                async fn plain(&self) {}
+            }
+
+            impl Ctl {
+                #[machinery::service]
+                async fn method(&self) {}
+            }
+        });
+
+        assert_eq!(result.services.len(), 1);
+        assert_eq!(
+            result.warnings,
+            [
+                "service crate::api::generic is ignored, services can't be methods of generic impls or trait impls",
+                "service crate::api::concrete is ignored, services can't be methods of generic impls or trait impls",
+                "service crate::api::from_trait is ignored, services can't be methods of generic impls or trait impls",
+            ]
+        );
+    }
+
+    #[test]
+    fn tags_internally_tagged_variants() {
+        let result = analyze(syn::parse_quote! {
+            #[machinery::message]
+            #[serde(tag = "type")]
+            enum Event {
+                Unit,
+                Named { a: u32 },
+                Newtype(Payload),
+                Empty(),
+                Pair(u32, u32),
+            }
+        });
+
+        assert_eq!(
+            result.messages[0].code,
+            "export type Event = \n\t{ type: \"Unit\" } |\n\t{ type: \"Named\", a: number } |\n\t\
+             { type: \"Newtype\" } & Payload |\n\tnever |\n\tnever;\n"
+        );
+    }
+
+    #[test]
+    fn adds_the_tag_of_internally_tagged_structs() {
+        let result = analyze(syn::parse_quote! {
+            #[machinery::message]
+            #[serde(tag = "type")]
+            struct Tagged {
+                a: u32,
+            }
+
+            #[machinery::message]
+            #[serde(tag = "kind", rename(serialize = "renamed", deserialize = "ignored"))]
+            struct Renamed {}
+        });
+
+        assert_eq!(
+            result.messages[0].code,
+            "export interface Tagged {\n\ttype: \"Tagged\",\n\ta: number,\n}\n"
+        );
+        assert_eq!(
+            result.messages[1].code,
+            "export interface Renamed {\n\tkind: \"renamed\",\n}\n"
+        );
+    }
+
+    #[test]
+    fn keeps_interceptors_of_other_crates_out_of_the_module() {
+        let result = analyze(syn::parse_quote! {
+            use crate::hooks::log;
+            use self::local::check as verify;
+
+            mod local {}
+            fn count() {}
+
+            #[machinery::service(
+                before = extcrate::audit,
+                before = ::extcrate::audit,
+                before = verify,
+                before = local::check,
+                after = count,
+                after = log,
+                after = super::hooks::log,
+            )]
+            async fn f() {}
+        });
+
+        let service = &result.services[0];
+        assert_eq!(
+            service.before,
+            [
+                "::extcrate::audit",
+                "::extcrate::audit",
+                "crate::api::local::check",
+                "crate::api::local::check",
+            ]
+        );
+        assert_eq!(
+            service.after,
+            [
+                "crate::api::count",
+                "crate::hooks::log",
+                "crate::hooks::log"
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_guards_of_other_crates_out_of_the_module() {
+        let result = analyze(syn::parse_quote! {
+            use shared_auth::AdminOnly;
+
+            struct Owner;
+
+            #[machinery::service(
+                guard = AdminOnly,
+                guard = shared_auth::Staff,
+                guard = Owner,
+                guard = "admin",
+            )]
+            async fn f() {}
+        });
+
+        let guards = result.services[0]
+            .guards
+            .iter()
+            .map(|guard| match guard {
+                ServiceGuard::Type(path) => path.as_str(),
+                ServiceGuard::Permission(permission) => permission.as_str(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            guards,
+            [
+                "::shared_auth::AdminOnly",
+                "::shared_auth::Staff",
+                "crate::api::Owner",
+                "admin",
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_the_cfg_of_services_and_their_modules() {
+        let file = syn::parse_quote! {
+            #[cfg(test)]
+            mod tests {
+                #[cfg(debug_assertions)]
+                #[machinery::service]
+                async fn f() {}
+            }
+
+            struct A;
+
+            #[cfg(not(test))]
+            impl A {
+                #[machinery::service]
+                async fn g(&self) {}
+            }
+        };
+        let result = analyze_file(&file, "crate", &["unix".to_owned()], &TypeMapper::default());
+
+        let cfg = result
+            .services
+            .iter()
+            .map(|service| service.cfg.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            cfg,
+            [
+                vec!["unix", "test", "debug_assertions"],
+                vec!["unix", "not (test)"],
+            ]
+        );
+    }
+
+    #[test]
+    fn resolves_injected_types_through_the_imports() {
+        let result = analyze(syn::parse_quote! {
+            use shared_auth::Session;
+            use crate::user::*;
+
+            struct Local;
+
+            #[machinery::service]
+            async fn f(user: User, session: Session, local: Local, list: Vec<u32>) {}
+        });
+
+        let type_paths = result.services[0]
+            .arguments
+            .iter()
+            .map(|arg| arg.type_paths.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            type_paths,
+            [
+                vec!["::User".to_owned(), "crate::user::User".to_owned()],
+                vec!["::shared_auth::Session".to_owned()],
+                vec!["crate::api::Local".to_owned()],
+                vec![],
+            ]
+        );
+    }
+
+    #[test]
+    fn tells_newtypes_from_tuples_by_their_fields() {
+        assert_eq!(
+            alias(syn::parse_quote!(
+                struct A(u32);
+            )),
+            Some("number".to_owned())
+        );
+        assert_eq!(
+            alias(syn::parse_quote!(
+                struct A(u32, #[serde(skip)] u32);
+            )),
+            Some("[number]".to_owned())
+        );
+        assert_eq!(
+            alias(syn::parse_quote!(
+                struct A(u32, String);
+            )),
+            Some("[number, string]".to_owned())
+        );
+        assert_eq!(
+            alias(syn::parse_quote!(
+                struct A;
+            )),
+            Some("null".to_owned())
+        );
+    }
+
+    #[test]
+    fn unwraps_transparent_structs() {
+        assert_eq!(
+            alias(syn::parse_quote!(
+                #[serde(transparent)]
+                struct A(u32, #[serde(skip)] u32);
+            )),
+            Some("number".to_owned())
+        );
+        assert_eq!(
+            alias(syn::parse_quote!(
+                #[serde(transparent)]
+                struct A {
+                    id: String,
+                    #[serde(skip)]
+                    cache: u32,
+                }
+            )),
+            Some("string".to_owned())
+        );
+        assert_eq!(
+            alias(syn::parse_quote!(
+                struct A {
+                    id: String,
+                }
+            )),
+            None
+        );
     }
 }
@@ -0,0 +1,401 @@
+use std::collections::HashMap;
+
+use syn::{punctuated::Punctuated, visit_mut::VisitMut, Attribute, Meta, Token};
+
+// options cargo doesn't describe to build scripts, items depending on them
+// are left to rustc
+const UNSEEN_OPTIONS: [&str; 2] = ["test", "debug_assertions"];
+
+/// The cfg options the crate is compiled with, read from the environment
+/// cargo gives build scripts.
+#[derive(Debug, Clone, Default)]
+pub struct CfgEnv {
+    features: Vec<String>,
+    options: HashMap<String, Vec<String>>,
+}
+
+impl CfgEnv {
+    /// Cargo describes the target to build scripts, but not everything about
+    /// the compilation: `CARGO_CFG_TEST` is never set and `debug_assertions`
+    /// follows the profile of the build script, which can differ from the
+    /// crate's. Predicates on them can't be decided here, the items keep
+    /// their `#[cfg]` and the generated code repeats it.
+    pub fn from_env() -> Self {
+        let mut env = CfgEnv::default();
+
+        for (key, value) in std::env::vars() {
+            if let Some(feature) = key.strip_prefix("CARGO_FEATURE_") {
+                env.features.push(feature.to_owned());
+            } else if let Some(name) = key.strip_prefix("CARGO_CFG_") {
+                // multi valued options like target_feature are comma separated
+                let values = value.split(',').map(|value| value.to_owned()).collect();
+                env.options.insert(name.to_ascii_lowercase(), values);
+            }
+        }
+
+        env
+    }
+
+    fn is_set(&self, name: &str, value: Option<&str>) -> bool {
+        match (name, value) {
+            // cargo uppercases feature names and replaces dashes
+            ("feature", Some(feature)) => {
+                let feature = feature.to_ascii_uppercase().replace('-', "_");
+                self.features.contains(&feature)
+            }
+            (name, None) => self.options.contains_key(name),
+            (name, Some(value)) => self
+                .options
+                .get(name)
+                .map(|values| values.iter().any(|v| v == value))
+                .unwrap_or(false),
+        }
+    }
+
+    /// Whether the predicate holds, `None` when it depends on what a build
+    /// script can't see.
+    pub fn eval(&self, predicate: &Meta) -> Option<bool> {
+        match predicate {
+            Meta::Path(path) => match path.get_ident() {
+                Some(name) if UNSEEN_OPTIONS.contains(&name.to_string().as_str()) => None,
+                Some(name) => Some(self.is_set(&name.to_string(), None)),
+                None => Some(false),
+            },
+            Meta::NameValue(meta) => match (meta.path.get_ident(), &meta.value) {
+                (
+                    Some(name),
+                    syn::Expr::Lit(syn::ExprLit {
+                        lit: syn::Lit::Str(value),
+                        ..
+                    }),
+                ) => Some(self.is_set(&name.to_string(), Some(&value.value()))),
+                _ => Some(false),
+            },
+            Meta::List(list) => {
+                let Ok(predicates) =
+                    list.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)
+                else {
+                    return Some(false);
+                };
+                let values = predicates
+                    .iter()
+                    .map(|predicate| self.eval(predicate))
+                    .collect::<Vec<_>>();
+
+                // a decided predicate can decide the others too
+                let combine = |decisive: bool| {
+                    if values.contains(&Some(decisive)) {
+                        Some(decisive)
+                    } else if values.contains(&None) {
+                        None
+                    } else {
+                        Some(!decisive)
+                    }
+                };
+
+                if list.path.is_ident("all") {
+                    combine(false)
+                } else if list.path.is_ident("any") {
+                    combine(true)
+                } else if list.path.is_ident("not") && predicates.len() == 1 {
+                    self.eval(&predicates[0]).map(|value| !value)
+                } else {
+                    Some(false)
+                }
+            }
+        }
+    }
+
+    // replaces `#[cfg_attr(predicate, a, b)]` with `#[a] #[b]` when the
+    // predicate holds, and drops it otherwise
+    fn expand_cfg_attr(&self, attrs: &mut Vec<Attribute>) {
+        let mut expanded = Vec::new();
+
+        for attr in attrs.drain(..) {
+            if !attr.path().is_ident("cfg_attr") {
+                expanded.push(attr);
+                continue;
+            }
+
+            let Ok((predicate, metas)) = attr.parse_args_with(|input: syn::parse::ParseStream| {
+                let predicate = input.parse::<Meta>()?;
+                input.parse::<Token![,]>()?;
+                let metas = Punctuated::<Meta, Token![,]>::parse_terminated(input)?;
+                Ok((predicate, metas))
+            }) else {
+                expanded.push(attr);
+                continue;
+            };
+
+            // attributes that depend on what a build script can't see are
+            // taken as disabled
+            if self.eval(&predicate) != Some(true) {
+                continue;
+            }
+
+            let mut nested = metas
+                .into_iter()
+                .map(|meta| Attribute {
+                    meta,
+                    ..attr.clone()
+                })
+                .collect::<Vec<_>>();
+
+            // cfg_attr can be nested
+            self.expand_cfg_attr(&mut nested);
+            expanded.extend(nested);
+        }
+
+        *attrs = expanded;
+    }
+
+    // the `#[cfg]` attributes that are decided are removed, the others are
+    // kept for rustc
+    fn is_enabled(&self, attrs: &mut Vec<Attribute>) -> bool {
+        self.expand_cfg_attr(attrs);
+
+        let mut enabled = true;
+        attrs.retain(|attr| {
+            if !attr.path().is_ident("cfg") {
+                return true;
+            }
+
+            match attr
+                .parse_args::<Meta>()
+                .map(|predicate| self.eval(&predicate))
+            {
+                Ok(Some(value)) => enabled &= value,
+                Ok(None) => return true,
+                Err(_) => enabled = false,
+            }
+            false
+        });
+
+        enabled
+    }
+
+    /// Removes everything that is compiled out from the file and expands the
+    /// `cfg_attr` attributes of what is left. The only `#[cfg]` attributes
+    /// left are the ones rustc has to decide.
+    pub fn strip(&self, file: &mut syn::File) {
+        Stripper(self).visit_file_mut(file);
+    }
+
+    fn retain<T>(
+        &self,
+        items: Punctuated<T, Token![,]>,
+        attrs: impl Fn(&mut T) -> &mut Vec<Attribute>,
+    ) -> Punctuated<T, Token![,]> {
+        items
+            .into_pairs()
+            .map(|pair| pair.into_value())
+            .filter_map(|mut item| self.is_enabled(attrs(&mut item)).then_some(item))
+            .collect()
+    }
+}
+
+/// The predicates of the `#[cfg]` attributes `strip` left for rustc.
+pub fn undecided(attrs: &[Attribute]) -> impl Iterator<Item = String> + '_ {
+    attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("cfg"))
+        .filter_map(|attr| attr.parse_args::<Meta>().ok())
+        .map(|predicate| quote::quote!(#predicate).to_string())
+}
+
+fn item_attrs(item: &mut syn::Item) -> Option<&mut Vec<Attribute>> {
+    match item {
+        syn::Item::Const(item) => Some(&mut item.attrs),
+        syn::Item::Enum(item) => Some(&mut item.attrs),
+        syn::Item::ExternCrate(item) => Some(&mut item.attrs),
+        syn::Item::Fn(item) => Some(&mut item.attrs),
+        syn::Item::ForeignMod(item) => Some(&mut item.attrs),
+        syn::Item::Impl(item) => Some(&mut item.attrs),
+        syn::Item::Macro(item) => Some(&mut item.attrs),
+        syn::Item::Mod(item) => Some(&mut item.attrs),
+        syn::Item::Static(item) => Some(&mut item.attrs),
+        syn::Item::Struct(item) => Some(&mut item.attrs),
+        syn::Item::Trait(item) => Some(&mut item.attrs),
+        syn::Item::TraitAlias(item) => Some(&mut item.attrs),
+        syn::Item::Type(item) => Some(&mut item.attrs),
+        syn::Item::Union(item) => Some(&mut item.attrs),
+        syn::Item::Use(item) => Some(&mut item.attrs),
+        _ => None,
+    }
+}
+
+fn impl_item_attrs(item: &mut syn::ImplItem) -> Option<&mut Vec<Attribute>> {
+    match item {
+        syn::ImplItem::Const(item) => Some(&mut item.attrs),
+        syn::ImplItem::Fn(item) => Some(&mut item.attrs),
+        syn::ImplItem::Type(item) => Some(&mut item.attrs),
+        syn::ImplItem::Macro(item) => Some(&mut item.attrs),
+        _ => None,
+    }
+}
+
+fn fn_arg_attrs(arg: &mut syn::FnArg) -> &mut Vec<Attribute> {
+    match arg {
+        syn::FnArg::Receiver(receiver) => &mut receiver.attrs,
+        syn::FnArg::Typed(typed) => &mut typed.attrs,
+    }
+}
+
+struct Stripper<'a>(&'a CfgEnv);
+
+impl VisitMut for Stripper<'_> {
+    fn visit_file_mut(&mut self, i: &mut syn::File) {
+        i.items
+            .retain_mut(|item| item_attrs(item).is_none_or(|attrs| self.0.is_enabled(attrs)));
+        syn::visit_mut::visit_file_mut(self, i);
+    }
+
+    fn visit_item_mod_mut(&mut self, i: &mut syn::ItemMod) {
+        if let Some((_, items)) = &mut i.content {
+            items.retain_mut(|item| item_attrs(item).is_none_or(|attrs| self.0.is_enabled(attrs)));
+        }
+        syn::visit_mut::visit_item_mod_mut(self, i);
+    }
+
+    fn visit_item_impl_mut(&mut self, i: &mut syn::ItemImpl) {
+        i.items
+            .retain_mut(|item| impl_item_attrs(item).is_none_or(|attrs| self.0.is_enabled(attrs)));
+        syn::visit_mut::visit_item_impl_mut(self, i);
+    }
+
+    fn visit_item_enum_mut(&mut self, i: &mut syn::ItemEnum) {
+        i.variants = self.0.retain(std::mem::take(&mut i.variants), |variant| {
+            &mut variant.attrs
+        });
+        syn::visit_mut::visit_item_enum_mut(self, i);
+    }
+
+    fn visit_fields_named_mut(&mut self, i: &mut syn::FieldsNamed) {
+        i.named = self
+            .0
+            .retain(std::mem::take(&mut i.named), |field| &mut field.attrs);
+        syn::visit_mut::visit_fields_named_mut(self, i);
+    }
+
+    fn visit_fields_unnamed_mut(&mut self, i: &mut syn::FieldsUnnamed) {
+        i.unnamed = self
+            .0
+            .retain(std::mem::take(&mut i.unnamed), |field| &mut field.attrs);
+        syn::visit_mut::visit_fields_unnamed_mut(self, i);
+    }
+
+    fn visit_signature_mut(&mut self, i: &mut syn::Signature) {
+        i.inputs = self.0.retain(std::mem::take(&mut i.inputs), fn_arg_attrs);
+        syn::visit_mut::visit_signature_mut(self, i);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env() -> CfgEnv {
+        CfgEnv {
+            features: vec!["LAMBDA".to_owned(), "DEBUG_LOG".to_owned()],
+            options: HashMap::from([
+                ("unix".to_owned(), vec!["".to_owned()]),
+                ("target_os".to_owned(), vec!["linux".to_owned()]),
+                (
+                    "target_feature".to_owned(),
+                    vec!["sse".to_owned(), "sse2".to_owned()],
+                ),
+            ]),
+        }
+    }
+
+    fn eval(predicate: &str) -> Option<bool> {
+        env().eval(&syn::parse_str(predicate).unwrap())
+    }
+
+    #[test]
+    fn evaluates_predicates() {
+        assert_eq!(eval("unix"), Some(true));
+        assert_eq!(eval("windows"), Some(false));
+        assert_eq!(eval("feature = \"lambda\""), Some(true));
+        assert_eq!(eval("feature = \"debug-log\""), Some(true));
+        assert_eq!(eval("feature = \"standalone\""), Some(false));
+        assert_eq!(eval("target_os = \"linux\""), Some(true));
+        assert_eq!(eval("target_os = \"macos\""), Some(false));
+        assert_eq!(eval("target_feature = \"sse2\""), Some(true));
+        assert_eq!(eval("all(unix, feature = \"lambda\")"), Some(true));
+        assert_eq!(eval("all(unix, windows)"), Some(false));
+        assert_eq!(eval("any(windows, unix)"), Some(true));
+        assert_eq!(eval("any()"), Some(false));
+        assert_eq!(eval("all()"), Some(true));
+        assert_eq!(eval("not(windows)"), Some(true));
+        assert_eq!(eval("not(unix, windows)"), Some(false));
+        assert_eq!(eval("unknown(unix)"), Some(false));
+    }
+
+    #[test]
+    fn leaves_test_and_debug_assertions_undecided() {
+        assert_eq!(eval("test"), None);
+        assert_eq!(eval("not(test)"), None);
+        assert_eq!(eval("debug_assertions"), None);
+        assert_eq!(eval("all(unix, test)"), None);
+        assert_eq!(eval("all(windows, test)"), Some(false));
+        assert_eq!(eval("any(unix, test)"), Some(true));
+        assert_eq!(eval("any(windows, test)"), None);
+    }
+
+    #[test]
+    fn expands_cfg_attr() {
+        let item: syn::ItemStruct = syn::parse_quote! {
+            #[cfg_attr(unix, derive(Debug), serde(rename = "a"))]
+            #[cfg_attr(windows, derive(Clone))]
+            #[cfg_attr(feature = "lambda", cfg_attr(unix, serde(skip)))]
+            #[doc = "kept"]
+            struct A;
+        };
+        let mut attrs = item.attrs;
+        env().expand_cfg_attr(&mut attrs);
+
+        let attrs = attrs
+            .iter()
+            .map(|attr| quote::quote!(#attr).to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            attrs,
+            [
+                "# [derive (Debug)]",
+                "# [serde (rename = \"a\")]",
+                "# [serde (skip)]",
+                "# [doc = \"kept\"]",
+            ]
+        );
+    }
+
+    #[test]
+    fn strips_disabled_items() {
+        let mut file: syn::File = syn::parse_quote! {
+            #[cfg(windows)]
+            fn a() {}
+            #[cfg(unix)]
+            #[cfg(all(unix, not(test)))]
+            fn b(#[cfg(feature = "standalone")] x: u32, y: u32) {}
+            struct C {
+                #[cfg(test)]
+                d: u32,
+                #[cfg(any(windows, debug_assertions))]
+                e: u32,
+                #[cfg(not(unix))]
+                f: u32,
+            }
+            #[cfg_attr(test, cfg(windows))]
+            fn g() {}
+        };
+        env().strip(&mut file);
+
+        assert_eq!(
+            quote::quote!(#file).to_string(),
+            "# [cfg (all (unix , not (test)))] fn b (y : u32) { } \
+             struct C { # [cfg (test)] d : u32 , # [cfg (any (windows , debug_assertions))] e : u32 } \
+             fn g () { }"
+        );
+    }
+}
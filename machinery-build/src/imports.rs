@@ -0,0 +1,190 @@
+use std::collections::{HashMap, HashSet};
+
+use syn::ext::IdentExt;
+
+/// The names brought into a module's scope by its `use` declarations.
+#[derive(Debug, Default, Clone)]
+pub struct Imports {
+    aliases: HashMap<String, Vec<String>>,
+    globs: Vec<Vec<String>>,
+    // the items the module declares itself
+    locals: HashSet<String>,
+}
+
+impl Imports {
+    pub fn from_items(items: &[syn::Item]) -> Self {
+        let mut imports = Imports::default();
+
+        for item in items {
+            let ident = match item {
+                syn::Item::Use(item) => {
+                    imports.add_tree(&item.tree, Vec::new());
+                    continue;
+                }
+                syn::Item::Const(item) => &item.ident,
+                syn::Item::Enum(item) => &item.ident,
+                syn::Item::Fn(item) => &item.sig.ident,
+                syn::Item::Mod(item) => &item.ident,
+                syn::Item::Static(item) => &item.ident,
+                syn::Item::Struct(item) => &item.ident,
+                syn::Item::Trait(item) => &item.ident,
+                syn::Item::Type(item) => &item.ident,
+                syn::Item::Union(item) => &item.ident,
+                _ => continue,
+            };
+            imports.locals.insert(ident.unraw().to_string());
+        }
+
+        imports
+    }
+
+    fn add_tree(&mut self, tree: &syn::UseTree, mut prefix: Vec<String>) {
+        match tree {
+            syn::UseTree::Path(path) => {
+                prefix.push(path.ident.unraw().to_string());
+                self.add_tree(&path.tree, prefix);
+            }
+            syn::UseTree::Name(name) if name.ident == "self" => {
+                if let Some(last) = prefix.last() {
+                    self.aliases.insert(last.clone(), prefix.clone());
+                }
+            }
+            syn::UseTree::Name(name) => {
+                let name = name.ident.unraw().to_string();
+                prefix.push(name.clone());
+                self.aliases.insert(name, prefix);
+            }
+            // `use foo as _;` doesn't bind a name
+            syn::UseTree::Rename(rename) if rename.rename == "_" => {}
+            syn::UseTree::Rename(rename) => {
+                if rename.ident != "self" {
+                    prefix.push(rename.ident.unraw().to_string());
+                }
+                self.aliases
+                    .insert(rename.rename.unraw().to_string(), prefix);
+            }
+            syn::UseTree::Glob(_) => self.globs.push(prefix),
+            syn::UseTree::Group(group) => group
+                .items
+                .iter()
+                .for_each(|tree| self.add_tree(tree, prefix.clone())),
+        }
+    }
+
+    /// Whether `name` is an item declared by the module.
+    pub fn is_local(&self, name: &str) -> bool {
+        self.locals.contains(name)
+    }
+
+    /// Whether a path `resolve` returned leads into the crate itself.
+    pub fn is_crate_path(&self, segments: &[String]) -> bool {
+        ["crate", "self", "super"].contains(&segments[0].as_str()) || self.is_local(&segments[0])
+    }
+
+    /// The full paths `path` may refer to. Explicit imports and the module's
+    /// own items shadow glob imports, so a path only has several candidates
+    /// when it goes through globs.
+    pub fn resolve(&self, path: &syn::Path) -> Vec<Vec<String>> {
+        let segments = path
+            .segments
+            .iter()
+            .map(|segment| segment.ident.unraw().to_string())
+            .collect::<Vec<_>>();
+
+        if path.leading_colon.is_some() || self.is_local(&segments[0]) {
+            return vec![segments];
+        }
+
+        if let Some(full_path) = self.aliases.get(&segments[0]) {
+            return vec![full_path.iter().chain(&segments[1..]).cloned().collect()];
+        }
+
+        let mut candidates = vec![segments.clone()];
+        candidates.extend(
+            self.globs
+                .iter()
+                .map(|glob| glob.iter().chain(&segments).cloned().collect()),
+        );
+
+        candidates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(imports: syn::File, path: &str) -> Vec<String> {
+        Imports::from_items(&imports.items)
+            .resolve(&syn::parse_str(path).unwrap())
+            .into_iter()
+            .map(|candidate| candidate.join("::"))
+            .collect()
+    }
+
+    #[test]
+    fn resolves_aliases() {
+        let imports: syn::File = syn::parse_quote! {
+            use machinery::service as svc;
+            use machinery::{self as m, inject::{Injectable, AsyncInjectable as Async}};
+            use crate::api::users;
+        };
+
+        assert_eq!(resolve(imports.clone(), "svc"), ["machinery::service"]);
+        assert_eq!(
+            resolve(imports.clone(), "m::message"),
+            ["machinery::message"]
+        );
+        assert_eq!(
+            resolve(imports.clone(), "Injectable"),
+            ["machinery::inject::Injectable"]
+        );
+        assert_eq!(
+            resolve(imports.clone(), "Async"),
+            ["machinery::inject::AsyncInjectable"]
+        );
+        assert_eq!(
+            resolve(imports.clone(), "users::User"),
+            ["crate::api::users::User"]
+        );
+        assert_eq!(resolve(imports, "::users::User"), ["users::User"]);
+    }
+
+    #[test]
+    fn skips_underscore_imports() {
+        let imports: syn::File = syn::parse_quote! {
+            use machinery::inject::Injectable as _;
+        };
+
+        assert_eq!(resolve(imports, "Injectable"), ["Injectable"]);
+    }
+
+    #[test]
+    fn lists_the_candidates_of_globs() {
+        let imports: syn::File = syn::parse_quote! {
+            use machinery::*;
+            use crate::user::*;
+        };
+
+        assert_eq!(
+            resolve(imports, "service"),
+            ["service", "machinery::service", "crate::user::service"]
+        );
+    }
+
+    #[test]
+    fn shadows_globs() {
+        let imports: syn::File = syn::parse_quote! {
+            use machinery::*;
+            use crate::hooks::service;
+            struct message;
+        };
+
+        assert_eq!(
+            resolve(imports.clone(), "service"),
+            ["crate::hooks::service"]
+        );
+        assert_eq!(resolve(imports.clone(), "message"), ["message"]);
+        assert_eq!(resolve(imports, "guard"), ["guard", "machinery::guard"]);
+    }
+}
@@ -1,2 +1,7 @@
 mod analyzer;
+mod cfg;
+mod imports;
+mod module_tree;
 pub mod pipeline;
+mod serde_attrs;
+mod types;
@@ -0,0 +1,301 @@
+use std::path::{Path, PathBuf};
+
+use syn::ext::IdentExt;
+
+use crate::cfg::{self, CfgEnv};
+use crate::pipeline::Error;
+
+pub struct SourceFile {
+    pub path: PathBuf,
+    pub module_path: String,
+    pub ast: syn::File,
+    // the `#[cfg]` predicates of the modules it is declared in that only
+    // rustc can decide
+    pub cfg: Vec<String>,
+}
+
+// where the files of a module's children are looked up
+struct ModuleDirs<'a> {
+    // directory of the source file the items are in, #[path] is relative to it
+    file_dir: &'a Path,
+    // directory of `mod child;` files, it includes inline modules and the
+    // module name of non mod.rs files
+    child_dir: PathBuf,
+    inline: bool,
+}
+
+fn parse_file(path: &Path, cfg: &CfgEnv) -> Result<syn::File, Error> {
+    let display = path.to_string_lossy().to_string();
+    let text =
+        std::fs::read_to_string(path).map_err(|_| Error::FailedToParseFile(display.clone()))?;
+
+    let mut ast = syn::parse_file(&text).map_err(|_| Error::FailedToParseFile(display))?;
+    cfg.strip(&mut ast);
+
+    Ok(ast)
+}
+
+fn path_attribute(item: &syn::ItemMod) -> Option<String> {
+    item.attrs.iter().find_map(|attr| match &attr.meta {
+        syn::Meta::NameValue(meta) if meta.path.is_ident("path") => match &meta.value {
+            syn::Expr::Lit(syn::ExprLit {
+                lit: syn::Lit::Str(path),
+                ..
+            }) => Some(path.value()),
+            _ => None,
+        },
+        _ => None,
+    })
+}
+
+fn resolve_module_file(item: &syn::ItemMod, name: &str, dirs: &ModuleDirs) -> Option<PathBuf> {
+    if let Some(path) = path_attribute(item) {
+        let base = if dirs.inline {
+            &dirs.child_dir
+        } else {
+            dirs.file_dir
+        };
+
+        return Some(base.join(path));
+    }
+
+    [
+        dirs.child_dir.join(format!("{}.rs", name)),
+        dirs.child_dir.join(name).join("mod.rs"),
+    ]
+    .into_iter()
+    .find(|path| path.is_file())
+}
+
+fn walk_items(
+    items: &[syn::Item],
+    module_path: &str,
+    module_cfg: &[String],
+    dirs: &ModuleDirs,
+    cfg: &CfgEnv,
+    files: &mut Vec<SourceFile>,
+) -> Result<(), Error> {
+    for item in items {
+        let syn::Item::Mod(item) = item else {
+            continue;
+        };
+
+        let name = item.ident.unraw().to_string();
+        let child_path = format!("{}::{}", module_path, name);
+        let child_cfg = module_cfg
+            .iter()
+            .cloned()
+            .chain(cfg::undecided(&item.attrs))
+            .collect::<Vec<_>>();
+
+        if let Some((_, items)) = &item.content {
+            let child_dirs = ModuleDirs {
+                file_dir: dirs.file_dir,
+                child_dir: dirs.child_dir.join(&name),
+                inline: true,
+            };
+
+            walk_items(items, &child_path, &child_cfg, &child_dirs, cfg, files)?;
+            continue;
+        }
+
+        let path = resolve_module_file(item, &name, dirs)
+            .ok_or_else(|| Error::ModuleNotFound(child_path.clone()))?;
+        let ast = parse_file(&path, cfg)?;
+
+        let file_dir = path.parent().unwrap().to_owned();
+        // mod.rs files and files loaded through #[path] own their directory
+        let owns_dir = path_attribute(item).is_some() || path.ends_with("mod.rs");
+        let child_dir = if owns_dir {
+            file_dir.clone()
+        } else {
+            file_dir.join(&name)
+        };
+
+        let child_dirs = ModuleDirs {
+            file_dir: &file_dir,
+            child_dir,
+            inline: false,
+        };
+
+        walk_items(&ast.items, &child_path, &child_cfg, &child_dirs, cfg, files)?;
+
+        files.push(SourceFile {
+            path,
+            module_path: child_path,
+            ast,
+            cfg: child_cfg,
+        });
+    }
+
+    Ok(())
+}
+
+/// Loads every file that is part of the crate, starting from its root and
+/// following `mod` declarations the way rustc does. Compiled out items are
+/// removed from the files.
+pub fn walk_crate(root: &Path, cfg: &CfgEnv) -> Result<Vec<SourceFile>, Error> {
+    let ast = parse_file(root, cfg)?;
+    let root_dir = root.parent().unwrap().to_owned();
+
+    let mut files = Vec::new();
+    let dirs = ModuleDirs {
+        file_dir: &root_dir,
+        child_dir: root_dir.clone(),
+        inline: false,
+    };
+
+    walk_items(&ast.items, "crate", &[], &dirs, cfg, &mut files)?;
+
+    files.insert(
+        0,
+        SourceFile {
+            path: root.to_owned(),
+            module_path: "crate".to_owned(),
+            ast,
+            cfg: Vec::new(),
+        },
+    );
+
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // writes the files of a crate to a directory of its own under the system
+    // temp dir, `main.rs` is the crate root
+    fn fixture(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!(
+            "machinery-module-tree-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&root);
+
+        for (path, content) in files {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+
+        root
+    }
+
+    // the module path and the file of every module, relative to the root
+    fn walk(name: &str, files: &[(&str, &str)]) -> Vec<(String, String)> {
+        let root = fixture(name, files);
+        let modules = walk_crate(&root.join("main.rs"), &CfgEnv::default())
+            .unwrap()
+            .into_iter()
+            .map(|file| {
+                let path = file.path.strip_prefix(&root).unwrap();
+                (file.module_path, path.to_string_lossy().replace('\\', "/"))
+            })
+            .collect();
+        std::fs::remove_dir_all(root).unwrap();
+
+        modules
+    }
+
+    fn modules(modules: &[(&str, &str)]) -> Vec<(String, String)> {
+        modules
+            .iter()
+            .map(|(module, path)| (module.to_string(), path.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn follows_mod_rs_and_named_files() {
+        assert_eq!(
+            walk(
+                "files",
+                &[
+                    ("main.rs", "mod api; mod util;"),
+                    ("api/mod.rs", "mod users;"),
+                    ("api/users.rs", ""),
+                    ("util.rs", "mod text;"),
+                    ("util/text.rs", ""),
+                ]
+            ),
+            modules(&[
+                ("crate", "main.rs"),
+                ("crate::api::users", "api/users.rs"),
+                ("crate::api", "api/mod.rs"),
+                ("crate::util::text", "util/text.rs"),
+                ("crate::util", "util.rs"),
+            ])
+        );
+    }
+
+    #[test]
+    fn follows_path_attributes() {
+        assert_eq!(
+            walk(
+                "path",
+                &[
+                    (
+                        "main.rs",
+                        "#[path = \"other/thing.rs\"] mod thing; mod inline { #[path = \"x.rs\"] mod x; }",
+                    ),
+                    ("other/thing.rs", "mod child;"),
+                    ("other/child.rs", ""),
+                    ("inline/x.rs", ""),
+                ]
+            ),
+            modules(&[
+                ("crate", "main.rs"),
+                ("crate::thing::child", "other/child.rs"),
+                ("crate::thing", "other/thing.rs"),
+                ("crate::inline::x", "inline/x.rs"),
+            ])
+        );
+    }
+
+    #[test]
+    fn follows_inline_modules() {
+        assert_eq!(
+            walk(
+                "inline",
+                &[
+                    ("main.rs", "mod outer { mod inner { mod leaf; } }"),
+                    ("outer/inner/leaf.rs", ""),
+                ]
+            ),
+            modules(&[
+                ("crate", "main.rs"),
+                ("crate::outer::inner::leaf", "outer/inner/leaf.rs"),
+            ])
+        );
+    }
+
+    #[test]
+    fn skips_compiled_out_modules_and_keeps_the_undecided_cfg() {
+        let root = fixture(
+            "cfg",
+            &[
+                (
+                    "main.rs",
+                    "#[cfg(windows)] mod missing; #[cfg(test)] mod tests { mod helpers; }",
+                ),
+                ("tests/helpers.rs", ""),
+            ],
+        );
+        let files = walk_crate(&root.join("main.rs"), &CfgEnv::default()).unwrap();
+        std::fs::remove_dir_all(root).unwrap();
+
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[1].module_path, "crate::tests::helpers");
+        assert_eq!(files[1].cfg, ["test"]);
+    }
+
+    #[test]
+    fn reports_missing_modules() {
+        let root = fixture("missing", &[("main.rs", "mod api;")]);
+        let result = walk_crate(&root.join("main.rs"), &CfgEnv::default());
+        std::fs::remove_dir_all(root).unwrap();
+
+        assert!(matches!(result, Err(Error::ModuleNotFound(module)) if module == "crate::api"));
+    }
+}
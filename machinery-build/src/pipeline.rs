@@ -2,7 +2,15 @@ use glob_match::glob_match;
 use std::path;
 use walkdir::WalkDir;
 
-use crate::analyzer::{analyze_file, AnalyzeResult, Service};
+use crate::analyzer::{
+    analyze_file, AnalyzeResult, Injectable, ReceiverKind, Service, ServiceGuard,
+};
+use crate::cfg::CfgEnv;
+use crate::module_tree::walk_crate;
+use crate::serde_attrs::RenameRule;
+use crate::types::{TsType, TypeMapper};
+
+pub use crate::types::{Int64Encoding, TypeMapping};
 
 const INTROSPECTION_NAMESPACE: &str = "machinery_introspection";
 
@@ -11,6 +19,31 @@ export interface Transport {
 	send(fn: string, args: string): Promise<string>;
 }
 
+export class MachineryError<D = unknown> extends Error {
+	code: string;
+	details?: D;
+	service?: string;
+	chain?: string[];
+
+	constructor(error: { message: string, code: string, details?: D, service?: string, chain?: string[] }) {
+		super(error.message);
+		this.name = \"MachineryError\";
+		this.code = error.code;
+		this.details = error.details;
+		this.service = error.service;
+		this.chain = error.chain;
+	}
+}
+
+// the typed error of a service, `details` holds the serialized error and
+// `service` the service that returned it. Each service with one gets a guard
+// that tells its type, eg. `isAccountAccountControllerRenameError` for
+// `account::AccountController::rename`
+export function isServiceError(error: unknown, service?: string): error is MachineryError {
+	return error instanceof MachineryError && error.code === \"service_error\"
+		&& (service === undefined || error.service === service);
+}
+
 const deepAssign: typeof Object.assign = (target: any, ...sources: any[]) => {
     for (const source of sources) {
         for (let k in source) {
@@ -25,53 +58,81 @@ const deepAssign: typeof Object.assign = (target: any, ...sources: any[]) => {
     return target;
 }
 
+";
 
+const HANDLE_RESULT: &str = "
 function handleResult(result: string) {
 	const json = JSON.parse(result);
 	if (json.error) {
-		throw new Error(json.error);
+		throw new MachineryError(json.error);
 	}
 	return json.result;
 }
 
-export type Option<T> = T | undefined;
-export type Result<T> = T;
-export type Vec<T> = T[];
-
-export type String = string;
-export type Void = void;
-
-export type u8 = number;
-export type u16 = number;
-export type u32 = number;
-export type u64 = number;
-export type usize = number;
+function stringifyArgs(args: any[]) {
+	return JSON.stringify(args);
+}
+";
 
-export type i8 = number;
-export type i16 = number;
-export type i32 = number;
-export type i64 = number;
-export type isize = number;
+// 64 bit integers travel as strings, the server lists where they are in the
+// result so they can be turned into bigints
+const HANDLE_RESULT_BIGINT: &str = "
+function reviveBigInts(value: any, paths: (string | number)[][]) {
+	for (const path of paths) {
+		if (path.length === 0) {
+			return BigInt(value);
+		}
+		let target = value;
+		for (const key of path.slice(0, -1)) {
+			target = target[key];
+		}
+		const key = path[path.length - 1];
+		target[key] = BigInt(target[key]);
+	}
+	return value;
+}
 
-export type f32 = number;
-export type f64 = number;
+function handleResult(result: string) {
+	const json = JSON.parse(result);
+	if (json.error) {
+		if (json.error.bigints) {
+			json.error.details = reviveBigInts(json.error.details, json.error.bigints);
+		}
+		throw new MachineryError(json.error);
+	}
+	return reviveBigInts(json.result, json.bigints ?? []);
+}
 
-export type bool = boolean;
+function stringifyArgs(args: any[]) {
+	return JSON.stringify(args, (_, value) => typeof value === \"bigint\" ? value.toString() : value);
+}
 ";
 
 #[derive(Debug)]
 pub enum Error {
     MissingExportDir,
+    MissingCrateRoot,
+    ModuleNotFound(String),
     FailedToParseFile(String),
     FailedToWriteFile(String),
+    DependencyCycle(Vec<String>),
 }
 
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
             Error::MissingExportDir => write!(f, "Missing export dir"),
+            Error::MissingCrateRoot => write!(f, "Missing crate root, expected main.rs or lib.rs"),
+            Error::ModuleNotFound(module) => write!(f, "Module not found: {}", module),
             Error::FailedToParseFile(file) => write!(f, "Failed to parse file: {}", file),
             Error::FailedToWriteFile(file) => write!(f, "Failed to write file: {}", file),
+            Error::DependencyCycle(types) => {
+                write!(
+                    f,
+                    "Injectables depend on each other: {}",
+                    types.join(" -> ")
+                )
+            }
         }
     }
 }
@@ -79,6 +140,7 @@ impl std::fmt::Display for Error {
 #[derive(Clone)]
 pub struct Pipeline {
     root_dir: String,
+    crate_root: Option<String>,
     base_crate_path: String,
     files: Vec<String>,
     export_dir: Option<String>,
@@ -87,6 +149,8 @@ pub struct Pipeline {
     custom_types: Vec<String>,
     custom_header: Option<String>,
     custom_footer: Option<String>,
+    int64_encoding: Int64Encoding,
+    type_mappings: Vec<TypeMapping>,
 }
 
 impl Pipeline {
@@ -106,6 +170,14 @@ impl Pipeline {
         self
     }
 
+    /// The file module paths are resolved from, `main.rs` or `lib.rs` in the
+    /// root dir by default.
+    pub fn with_crate_root(&mut self, path: &str) -> &mut Self {
+        self.crate_root = Some(path.to_owned());
+
+        self
+    }
+
     pub fn enable_debug_comments(&mut self) -> &mut Self {
         self.debug_comments = true;
 
@@ -142,12 +214,149 @@ impl Pipeline {
         self
     }
 
+    pub fn with_int64_encoding(&mut self, encoding: Int64Encoding) -> &mut Self {
+        self.int64_encoding = encoding;
+
+        self
+    }
+
+    pub fn with_type_mapping(&mut self, mapping: TypeMapping) -> &mut Self {
+        self.type_mappings.push(mapping);
+
+        self
+    }
+
     pub fn export_to_dir(&mut self, path: &str) -> &mut Self {
         self.export_dir = Some(path.to_owned());
 
         self
     }
 
+    // every named type has to be a message or a custom type, anything else
+    // would not exist in the generated client
+    fn check_types(&self, result: &AnalyzeResult) -> Vec<String> {
+        let known_types = result
+            .messages
+            .iter()
+            .map(|message| message.name.clone())
+            .chain(self.custom_types.iter().map(|custom_type| {
+                let name = custom_type.split('=').next().unwrap();
+                name.split('<').next().unwrap().trim().to_owned()
+            }))
+            .collect::<Vec<_>>();
+
+        let mut warnings = Vec::new();
+        let mut check = |ty: &TsType, owner: &str| {
+            ty.walk(&mut |ty| match ty {
+                TsType::Unsupported(ty) => warnings.push(format!(
+                    "`{}` used in {} has no TypeScript representation",
+                    ty, owner
+                )),
+                TsType::Reference { name, .. } if !known_types.contains(name) => {
+                    warnings.push(format!(
+                        "`{}` used in {} is not a machinery message or custom type",
+                        name, owner
+                    ))
+                }
+                _ => {}
+            })
+        };
+
+        for message in &result.messages {
+            for ty in &message.types {
+                check(ty, &format!("message {}", message.name));
+            }
+        }
+
+        for service in &result.services {
+            let owner = format!("service {}::{}", service.location, service.name);
+
+            for arg in service.arguments.iter().filter(|arg| !arg.injected) {
+                check(&arg.ty, &owner);
+            }
+            check(&service.return_type, &owner);
+        }
+
+        let is_injectable =
+            |type_paths: &[String]| find_injectable(&result.injectables, type_paths).is_some();
+
+        for service in &result.services {
+            if let (Some(receiver), Some(_)) = (&service.owner, service.receiver) {
+                if !is_injectable(std::slice::from_ref(receiver)) {
+                    warnings.push(format!(
+                        "`{}` used as the receiver of service {}::{} doesn't implement Injectable or AsyncInjectable",
+                        receiver, service.location, service.name
+                    ));
+                }
+            }
+
+            for arg in service.arguments.iter().filter(|arg| arg.injected) {
+                if !is_injectable(&arg.type_paths) {
+                    warnings.push(format!(
+                        "`{}` injected into service {}::{} isn't a known injectable, it is assumed to implement Injectable",
+                        arg.name, service.location, service.name
+                    ));
+                }
+            }
+        }
+
+        let permissions = result.services.iter().flat_map(|service| {
+            service
+                .permissions()
+                .map(move |permission| (service, permission))
+        });
+        match result.permission_checkers.as_slice() {
+            [] => {
+                for (service, permission) in permissions {
+                    warnings.push(format!(
+                        "the `{}` permission of service {}::{} is always denied, no type implements Permissions",
+                        permission, service.location, service.name
+                    ));
+                }
+            }
+            [_] => {}
+            [checker, ..] => warnings.push(format!(
+                "Permissions is implemented more than once, `{}` checks the permissions",
+                checker
+            )),
+        }
+
+        warnings
+    }
+
+    // imports of the type mappings that ended up in the client
+    fn used_imports(&self, result: &AnalyzeResult) -> Vec<String> {
+        let mut imports = Vec::new();
+        let mut collect = |ty: &TsType| {
+            ty.walk(&mut |ty| {
+                if let TsType::Mapped {
+                    import: Some(import),
+                    ..
+                } = ty
+                {
+                    if !imports.contains(import) {
+                        imports.push(import.clone());
+                    }
+                }
+            })
+        };
+
+        for message in &result.messages {
+            message.types.iter().for_each(&mut collect);
+        }
+
+        for service in &result.services {
+            service
+                .arguments
+                .iter()
+                .filter(|arg| !arg.injected)
+                .for_each(|arg| collect(&arg.ty));
+            collect(&service.return_type);
+        }
+
+        imports
+    }
+
     pub fn build_ts_client(&mut self, result: &AnalyzeResult) -> Result<(), Error> {
         let export_dir = self.export_dir.clone().ok_or(Error::MissingExportDir)?;
         let export_dir = path::Path::new(&export_dir);
@@ -161,7 +370,16 @@ impl Pipeline {
         if self.custom_header.is_some() {
             code.push_str(&self.custom_header.clone().unwrap());
         }
+
+        for import in self.used_imports(result) {
+            code.push_str(&format!("\n{}", import));
+        }
+
         code.push_str(HEADER);
+        code.push_str(match self.int64_encoding {
+            Int64Encoding::BigInt => HANDLE_RESULT_BIGINT,
+            _ => HANDLE_RESULT,
+        });
 
         for custom_type in &self.custom_types {
             code.push_str(&format!("export type {};\n", custom_type));
@@ -179,6 +397,8 @@ impl Pipeline {
         );
 
         let mut obj_count: u32 = 1;
+        let mut permissions = Vec::new();
+        let mut error_guards = Vec::new();
 
         // create a tree from the services location eg crate::api::greeting::hello -> { crate: { api: { greeting: { hello: {} } } } }
         for service in &result.services {
@@ -194,31 +414,93 @@ impl Pipeline {
             let obj_path = format!("{{ {}: {{", obj_path);
             let obj_end = "}".repeat(base_path.split("::").count() + 1);
 
-            let service_args = service
+            // trailing Option arguments can be left out by the caller, they are
+            // sent as null anyway
+            let wire_args = service
                 .arguments
-                .clone()
-                .into_iter()
-                .map(|arg| {
-                    let arg = arg.split(":").collect::<Vec<_>>();
-                    (arg[0].to_owned(), arg[1].to_owned())
+                .iter()
+                .filter(|arg| !arg.injected)
+                .collect::<Vec<_>>();
+
+            let required_args = wire_args
+                .iter()
+                .rposition(|arg| !matches!(arg.ty, TsType::Nullable(_)))
+                .map(|index| index + 1)
+                .unwrap_or(0);
+
+            let fn_args = wire_args
+                .iter()
+                .enumerate()
+                .map(|(index, arg)| {
+                    let optional = if index >= required_args { "?" } else { "" };
+                    format!("{}{}: {}", arg.name, optional, arg.ty)
                 })
-                .collect::<Vec<(String, String)>>();
+                .collect::<Vec<_>>()
+                .join(", ");
 
-            let call_args = service_args
-                .into_iter()
-                .map(|(name, _)| format!("{name} ?? null"))
+            let call_args = wire_args
+                .iter()
+                .map(|arg| format!("{} ?? null", arg.name))
                 .collect::<Vec<_>>()
                 .join(", ");
 
+            let call_name = format!(
+                "{}::{}",
+                service.location.replace("crate::", ""),
+                service.name
+            );
+
+            let mut tags = service
+                .permissions()
+                .map(|permission| format!("@permission {}", permission))
+                .collect::<Vec<_>>();
+            if let Some(error_type) = typed_error(result, service) {
+                tags.push(format!("@throws {{MachineryError<{}>}}", error_type));
+
+                let guard_name = base_path
+                    .split("::")
+                    .chain([service.name.as_str()])
+                    .map(|segment| RenameRule::Pascal.apply_to_field(segment))
+                    .collect::<String>();
+                error_guards.push(format!(
+                    "\nexport function is{}Error(error: unknown): error is MachineryError<{}> & {{ details: {} }} {{\n\treturn isServiceError(error, {:?});\n}}\n",
+                    guard_name, error_type, error_type, call_name
+                ));
+            }
+            // tags are only picked up at the start of a line
+            let doc = match tags.as_slice() {
+                [] => "".to_owned(),
+                [tag] => format!("/** {} */ ", tag),
+                tags => format!(
+                    "/**\n{}\t */ ",
+                    tags.iter()
+                        .map(|tag| format!("\t * {}\n", tag))
+                        .collect::<String>()
+                ),
+            };
+
+            if service.permissions().next().is_some() {
+                permissions.push(format!(
+                    "\t\"{}\": [{}],\n",
+                    call_name,
+                    service
+                        .permissions()
+                        .map(|permission| format!("{:?}", permission))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+
             code.push_str(&format!(
-                "\tlet obj{} = deepAssign(obj{}, {} async {}({}): Promise<{}> {{ return handleResult(await transport.send(\"{}\", JSON.stringify([{}]))); }} {});\n",
+                "\tlet obj{} = deepAssign(obj{}, {} {}async {}({}): Promise<{}> {{ return handleResult(await transport.send(\"{}\", stringifyArgs([{}]))); }} {});\n",
                 obj_count,
                 obj_count - 1,
                 obj_path,
+                doc,
                 service.name,
-                service.arguments.join(", "),
+                fn_args,
                 service.return_type,
-                format!("{}::{}", service.location.replace("crate::", ""), service.name),
+                call_name,
                 call_args,
                 obj_end
             ));
@@ -228,6 +510,15 @@ impl Pipeline {
 
         code.push_str(&format!("\treturn obj{};\n}};\n", obj_count - 1));
 
+        // the permissions each service requires, by the name it is called with
+        code.push_str(&format!(
+            "\nexport const permissions: Record<string, string[]> = {{\n{}}};\n",
+            permissions.concat()
+        ));
+
+        // the guards of the services with typed errors, for their callers
+        code.push_str(&error_guards.concat());
+
         if self.custom_footer.is_some() {
             code.push_str(&self.custom_footer.clone().unwrap());
         }
@@ -269,8 +560,15 @@ impl Pipeline {
             result.services.push(Service {
                 name: "ts_client".to_owned(),
                 location: INTROSPECTION_NAMESPACE.to_owned(),
+                owner: None,
+                receiver: None,
                 arguments: vec![],
-                return_type: "String".to_owned(),
+                return_type: TsType::String,
+                error_type: None,
+                before: vec![],
+                after: vec![],
+                guards: vec![],
+                cfg: vec![],
             });
         }
 
@@ -280,81 +578,219 @@ impl Pipeline {
             let base_name = &full_name.replace(&format!("{}::", self.base_crate_path), "");
             let ident_name = &base_name.replace("::", "_");
             let call_name = full_name.replace("crate::", "");
+            let cfg = service
+                .cfg
+                .iter()
+                .map(|predicate| format!("#[cfg({predicate})] "))
+                .collect::<String>();
 
             handlers.push(format!(
-                "\"{call_name}\" => handle_{ident_name}(&ctx, json_input).await,"
+                "{cfg}\"{call_name}\" => handle_{ident_name}(&ctx, json_input).await,"
             ));
 
-            let args = service.arguments.clone().into_iter().map(|arg| {
-                let arg = arg.split(":").collect::<Vec<_>>();
-                (arg[0].to_owned(), arg[1].to_owned())
-            });
+            let arg_names = service
+                .arguments
+                .iter()
+                .filter(|arg| !arg.injected)
+                .map(|arg| format!("arg_{}", arg.name))
+                .collect::<Vec<_>>();
+
+            let (decode_fn, encode_fn, encoded_output, encoded_error, error_bigints) = match self.int64_encoding {
+                Int64Encoding::Number => (
+                    "machinery::json::from_str",
+                    "machinery::json::to_string",
+                    "Ok(output) => machinery::MachineryResponse::ok(format!(\"{{ \\\"result\\\": {} }}\", output))",
+                    "Ok(details)",
+                    "None",
+                ),
+                Int64Encoding::String => (
+                    "machinery::int64::from_str",
+                    "machinery::int64::to_string",
+                    "Ok(output) => machinery::MachineryResponse::ok(format!(\"{{ \\\"result\\\": {} }}\", output))",
+                    "Ok(details)",
+                    "None",
+                ),
+                Int64Encoding::BigInt => (
+                    "machinery::int64::from_str",
+                    "machinery::int64::to_string_with_paths",
+                    "Ok((output, bigints)) => machinery::MachineryResponse::ok(format!(\"{{ \\\"result\\\": {}, \\\"bigints\\\": {} }}\", output, bigints))",
+                    "Ok((details, bigints))",
+                    "Some(&bigints)",
+                ),
+            };
+
+            let intercepted = !service.before.is_empty() || !service.after.is_empty();
+
+            // services without arguments ignore the input, unless it is intercepted
+            let (input_name, decode_input) = if arg_names.is_empty() {
+                match intercepted {
+                    true => ("json_input", "".to_owned()),
+                    false => ("_json_input", "".to_owned()),
+                }
+            } else {
+                let arg_names_str = arg_names.join(", ");
 
-            if args.len() == 0 {
-                code.push_str(&format!(
+                (
+                    "json_input",
+                    format!(
+                        "
+        let ({arg_names_str},) = match {decode_fn}(&json_input) {{
+            Ok(args) => args,
+            Err(err) => return machinery::error::ErrorResponse::new(\"bad_input\", format!(\"Failed to deserialize input: {{}}\", err)).into_response(),
+        }};
+"
+                    ),
+                )
+            };
+
+            // guards run before anything else, calls that aren't allowed are
+            // never decoded
+            let mut inject_code = String::new();
+            for guard in &service.guards {
+                let check = match (guard, result.permission_checkers.first()) {
+                    (ServiceGuard::Type(path), _) => {
+                        format!("<{path} as machinery::guard::Guard>::check(ctx).await")
+                    }
+                    (ServiceGuard::Permission(permission), Some(checker)) => format!(
+                        "<{checker} as machinery::guard::Permissions>::check(ctx, {permission:?}).await"
+                    ),
+                    (ServiceGuard::Permission(permission), None) => {
+                        format!("machinery::guard::__internal::unchecked({permission:?})")
+                    }
+                };
+                inject_code.push_str(&format!(
                     "
-    async fn handle_{ident_name}(ctx: &machinery::context::Context, _json_input: String) -> String {{
-        let res_output = {full_name}(ctx).await;
-        if res_output.is_err()  {{
-            return format!(\"{{{{ \\\"error\\\": \\\"{{}}\\\" }}}}\", res_output.unwrap_err());
-        }}
-        
-        let output = res_output.unwrap();
-        let output = machinery::json::to_string(&output);
-
-        if output.is_ok() {{
-            return format!(\"{{{{ \\\"result\\\": {{}} }}}}\", output.unwrap());
+        if let Err(err) = {check} {{
+            return machinery::error::ErrorResponse::from_error(err, \"forbidden\").into_response();
         }}
-
-        return \"{{ \\\"error\\\": \\\"Failed to serialize output\\\" }}\".to_owned();
-    }}
 "
                 ));
-            } else {
-                let arg_names = args
-                    .clone()
-                    .map(|(name, _)| format!("arg_{name}"))
-                    .collect::<Vec<_>>();
-                let arg_names_str = arg_names.join(", ");
+            }
 
-                // let arg_types = args.map(|(_, ty)| ty).collect::<Vec<_>>();
-                // let arg_types_str = arg_types.join(", ");
+            // the receiver and injected arguments are resolved before the
+            // input is looked at. `&self` shares the injected value of the
+            // request, `&mut self` and `self` get one of their own
+            let receiver_arg = match (&service.owner, service.receiver) {
+                (Some(owner), Some(kind)) => {
+                    let (binding, receiver_arg, shared) = match kind {
+                        ReceiverKind::Ref => ("receiver", "&receiver", true),
+                        ReceiverKind::RefMut => ("mut receiver", "&mut receiver", false),
+                        ReceiverKind::Value => ("receiver", "receiver", false),
+                    };
+                    let injectable =
+                        find_injectable(&result.injectables, std::slice::from_ref(owner));
+                    let is_async = injectable.is_some_and(|injectable| injectable.is_async);
+                    inject_code.push_str(&inject(owner, is_async, binding, shared));
+
+                    Some(receiver_arg.to_owned())
+                }
+                _ => None,
+            };
+
+            // types that weren't found in the crate are left to inference
+            // from the service's signature and assumed to be sync
+            for arg in service.arguments.iter().filter(|arg| arg.injected) {
+                let binding = format!("arg_{}", arg.name);
+                let (ty, is_async) = match find_injectable(&result.injectables, &arg.type_paths) {
+                    Some(injectable) => (injectable.type_path.as_str(), injectable.is_async),
+                    None => ("_", false),
+                };
+                inject_code.push_str(&inject(ty, is_async, &binding, arg.shared));
+            }
 
-                code.push_str(&format!(
+            let call_args = receiver_arg
+                .into_iter()
+                .chain(std::iter::once("ctx".to_owned()))
+                .chain(
+                    service
+                        .arguments
+                        .iter()
+                        .map(|arg| format!("arg_{}", arg.name)),
+                )
+                .collect::<Vec<_>>()
+                .join(", ");
+            let rust_path = service.rust_path();
+            // typed errors are sent as they are, anything else goes through anyhow
+            let error_output = match typed_error(result, service) {
+                Some(_) => format!(
+                    "match {encode_fn}(&err) {{
+                {encoded_error} => machinery::error::ErrorResponse::service_error({call_name:?}, &details, {error_bigints}).into_response(),
+                Err(_) => machinery::error::ErrorResponse::new(\"internal\", \"Failed to serialize error\").into_response(),
+            }}"
+                ),
+                None => "machinery::error::ErrorResponse::from_error(err, \"internal\").into_response()"
+                    .to_owned(),
+            };
+            // interceptors see the call once its input is known to be valid
+            let mut intercept_code = String::new();
+            if intercepted {
+                intercept_code.push_str(&format!(
                     "
-    async fn handle_{ident_name}(ctx: &machinery::context::Context, json_input: String) -> String {{
-        let rest_input = machinery::json::from_str(&json_input);
-        if rest_input.is_err() {{
-            return \"{{ \\\"error\\\": \\\"Failed to deserialize input\\\" }}\".to_owned();
-        }}
-        let ({arg_names_str},) = rest_input.unwrap();
-
-        let res_output = {full_name}(ctx, {arg_names_str}).await;
-        if res_output.is_err() {{
-            return format!(\"{{{{ \\\"error\\\": \\\"{{}}\\\" }}}}\", res_output.unwrap_err());
+        let call = machinery::intercept::__internal::call(\"{call_name}\", &json_input);
+"
+                ));
+            }
+            for before in &service.before {
+                intercept_code.push_str(&format!(
+                    "
+        if let Err(err) = {before}(ctx, &call).await {{
+            return machinery::error::ErrorResponse::from_error(err, \"internal\").into_response();
         }}
-
-        let output = res_output.unwrap();
-        let output = machinery::json::to_string(&output);
-
-        if output.is_ok() {{
-            return format!(\"{{{{ \\\"result\\\": {{}} }}}}\", output.unwrap());
+"
+                ));
+            }
+            // the response is kept for the interceptors that run after the call
+            let (response_start, response_end) = match service.after.is_empty() {
+                true => ("", String::new()),
+                false => {
+                    let after_code = service
+                        .after
+                        .iter()
+                        .map(|after| {
+                            format!(
+                                "
+        if let Err(err) = {after}(ctx, &call, &response).await {{
+            return machinery::error::ErrorResponse::from_error(err, \"internal\").into_response();
         }}
+"
+                            )
+                        })
+                        .collect::<String>();
+
+                    (
+                        "let response = ",
+                        format!(";\n{after_code}\n        response"),
+                    )
+                }
+            };
+            // handler names of methods contain the type name
+            let allow_lint = match service.owner {
+                Some(_) => "\n    #[allow(non_snake_case)]",
+                None => "",
+            };
 
-        return \"{{ \\\"error\\\": \\\"Failed to serialize output\\\" }}\".to_owned();
+            code.push_str(&format!(
+                "{allow_lint}
+    {cfg}async fn handle_{ident_name}(ctx: &machinery::context::Context, {input_name}: String) -> machinery::MachineryResponse {{{inject_code}{decode_input}{intercept_code}
+        {response_start}match {rust_path}({call_args}).await {{
+            Ok(output) => match {encode_fn}(&output) {{
+                {encoded_output},
+                Err(_) => machinery::error::ErrorResponse::new(\"internal\", \"Failed to serialize output\").into_response(),
+            }},
+            Err(err) => {error_output},
+        }}{response_end}
     }}
 "
-                ));
-            }
+            ));
         }
 
         code.push_str(
             format!(
                 "
-    pub async fn handle(ctx: machinery::context::Context, fn_name: String, json_input: String) -> String {{
+    pub async fn handle(ctx: std::sync::Arc<machinery::context::Context>, fn_name: String, json_input: String) -> machinery::MachineryResponse {{
         match fn_name.as_str() {{
 {}
-            _ => return \"{{ \\\"error\\\": \\\"Unknown function\\\" }}\".to_owned(),
+            _ => machinery::error::ErrorResponse::new(\"unknown_function\", \"Unknown function\").into_response(),
         }}
     }}
 }}
@@ -371,20 +807,79 @@ impl Pipeline {
     }
 
     pub fn build(&mut self) -> Result<(), Error> {
+        let mapper = TypeMapper {
+            int64_encoding: self.int64_encoding,
+            mappings: self.type_mappings.clone(),
+        };
+
         let mut combined_result = AnalyzeResult {
             file_location: "crate".to_owned(),
             services: Vec::new(),
             messages: Vec::new(),
+            injectables: Vec::new(),
+            permission_checkers: Vec::new(),
+            warnings: Vec::new(),
         };
 
-        for file_path in &self.files {
-            let file_text = std::fs::read_to_string(file_path).unwrap();
+        let crate_root = match &self.crate_root {
+            Some(crate_root) => path::PathBuf::from(crate_root),
+            None => ["main.rs", "lib.rs"]
+                .iter()
+                .map(|file| path::Path::new(&self.root_dir).join(file))
+                .find(|path| path.is_file())
+                .ok_or(Error::MissingCrateRoot)?,
+        };
 
-            let ast = syn::parse_file(&file_text).unwrap();
+        // files that aren't reachable from the crate root aren't compiled, the
+        // ones from add_files narrow the analysis down further
+        let selected = self
+            .files
+            .iter()
+            .filter_map(|file| path::Path::new(file).canonicalize().ok())
+            .collect::<Vec<_>>();
+
+        for file in walk_crate(&crate_root, &CfgEnv::from_env())? {
+            let is_selected = file
+                .path
+                .canonicalize()
+                .map(|path| selected.contains(&path))
+                .unwrap_or(false);
+
+            // injectables and permission checkers are needed wherever they are,
+            // services and messages only from the selected files
+            let result = analyze_file(&file.ast, &file.module_path, &file.cfg, &mapper);
+            combined_result.injectables.extend(result.injectables);
+            combined_result
+                .permission_checkers
+                .extend(result.permission_checkers);
+            if !self.files.is_empty() && !is_selected {
+                continue;
+            }
 
-            let result = analyze_file(&ast, &file_path);
             combined_result.services.extend(result.services);
             combined_result.messages.extend(result.messages);
+            combined_result.warnings.extend(result.warnings);
+        }
+
+        // arguments of injectable types don't need to be marked
+        for service in &mut combined_result.services {
+            for arg in &mut service.arguments {
+                arg.injected |=
+                    find_injectable(&combined_result.injectables, &arg.type_paths).is_some();
+            }
+        }
+
+        // injecting any of them would never finish
+        if let Some(cycle) = find_cycle(&combined_result.injectables) {
+            return Err(Error::DependencyCycle(cycle));
+        }
+
+        for warning in combined_result
+            .warnings
+            .iter()
+            .chain(&self.check_types(&combined_result))
+        {
+            println!("cargo:warning={}", warning);
         }
 
         self.build_ts_client(&combined_result)?;
@@ -394,9 +889,100 @@ impl Pipeline {
     }
 }
 
+// the error type of a service when it is a message the client knows
+fn typed_error<'a>(result: &AnalyzeResult, service: &'a Service) -> Option<&'a TsType> {
+    match &service.error_type {
+        Some(ty @ TsType::Reference { name, .. })
+            if result.messages.iter().any(|message| &message.name == name) =>
+        {
+            Some(ty)
+        }
+        _ => None,
+    }
+}
+
+// the first chain of injectables that leads back to its start, eg. `A -> B -> A`
+fn find_cycle(injectables: &[Injectable]) -> Option<Vec<String>> {
+    let mut done = Vec::new();
+
+    injectables.iter().find_map(|injectable| {
+        visit_dependencies(
+            injectables,
+            &injectable.type_path,
+            &mut Vec::new(),
+            &mut done,
+        )
+    })
+}
+
+fn visit_dependencies<'a>(
+    injectables: &'a [Injectable],
+    type_path: &'a str,
+    chain: &mut Vec<&'a str>,
+    done: &mut Vec<&'a str>,
+) -> Option<Vec<String>> {
+    if let Some(start) = chain.iter().position(|ty| *ty == type_path) {
+        let mut cycle = chain[start..]
+            .iter()
+            .map(|ty| ty.to_string())
+            .collect::<Vec<_>>();
+        cycle.push(type_path.to_owned());
+        return Some(cycle);
+    }
+    if done.contains(&type_path) {
+        return None;
+    }
+
+    chain.push(type_path);
+    let dependencies = injectables
+        .iter()
+        .filter(|injectable| injectable.type_path == type_path)
+        .flat_map(|injectable| &injectable.dependencies);
+    for dependency in dependencies {
+        if let Some(cycle) = visit_dependencies(injectables, dependency, chain, done) {
+            return Some(cycle);
+        }
+    }
+    chain.pop();
+    done.push(type_path);
+
+    None
+}
+
+// the injectable of the crate any of `type_paths` refers to
+fn find_injectable<'a>(
+    injectables: &'a [Injectable],
+    type_paths: &[String],
+) -> Option<&'a Injectable> {
+    injectables
+        .iter()
+        .find(|injectable| type_paths.contains(&injectable.type_path))
+}
+
+// binds an injectable for the handler, the `Arc` of the request when it is
+// `shared` or else a value of its own
+fn inject(ty: &str, is_async: bool, binding: &str, shared: bool) -> String {
+    let function = if shared { "inject" } else { "build" };
+    let inject = if is_async {
+        format!("machinery::inject::__internal::{function}_async::<{ty}>(ctx).await")
+    } else {
+        format!("machinery::inject::__internal::{function}::<{ty}>(ctx)")
+    };
+
+    format!(
+        "
+        let {binding} = match {inject} {{
+            Ok(injected) => injected,
+            Err(err) => return machinery::error::ErrorResponse::from_error(err, \"injection_failed\").into_response(),
+        }};
+"
+    )
+}
+
 pub fn default(root_dir: &str) -> Pipeline {
     Pipeline {
         root_dir: root_dir.to_owned(),
+        crate_root: None,
         files: Vec::new(),
         export_dir: None,
         base_crate_path: "crate".to_owned(),
@@ -405,5 +991,70 @@ pub fn default(root_dir: &str) -> Pipeline {
         custom_types: vec![],
         custom_header: None,
         custom_footer: None,
+        int64_encoding: Int64Encoding::Number,
+        type_mappings: vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn injectable(type_path: &str, dependencies: &[&str]) -> Injectable {
+        Injectable {
+            type_path: type_path.to_owned(),
+            is_async: false,
+            dependencies: dependencies.iter().map(|dep| dep.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn finds_self_cycles() {
+        let injectables = [injectable("crate::A", &["crate::A"])];
+
+        assert_eq!(
+            find_cycle(&injectables),
+            Some(vec!["crate::A".to_owned(), "crate::A".to_owned()])
+        );
+    }
+
+    #[test]
+    fn finds_cycles_between_injectables() {
+        let injectables = [
+            injectable("crate::A", &["crate::B"]),
+            injectable("crate::B", &["crate::A"]),
+        ];
+
+        assert_eq!(
+            find_cycle(&injectables),
+            Some(vec![
+                "crate::A".to_owned(),
+                "crate::B".to_owned(),
+                "crate::A".to_owned()
+            ])
+        );
+    }
+
+    #[test]
+    fn allows_shared_dependencies() {
+        // A -> B -> D and A -> C -> D
+        let injectables = [
+            injectable("crate::A", &["crate::B", "crate::C"]),
+            injectable("crate::B", &["crate::D"]),
+            injectable("crate::C", &["crate::D"]),
+            injectable("crate::D", &[]),
+        ];
+
+        assert_eq!(find_cycle(&injectables), None);
+    }
+
+    #[test]
+    fn ignores_unknown_dependencies() {
+        let injectables = [
+            injectable("crate::A", &["other::Config"]),
+            injectable("crate::B", &["crate::A", "other::Config"]),
+        ];
+
+        assert_eq!(find_cycle(&injectables), None);
     }
 }
@@ -0,0 +1,311 @@
+use syn::{Attribute, LitStr};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RenameRule {
+    Lower,
+    Upper,
+    Pascal,
+    Camel,
+    Snake,
+    ScreamingSnake,
+    Kebab,
+    ScreamingKebab,
+}
+
+impl RenameRule {
+    fn from_str(rule: &str) -> Option<Self> {
+        match rule {
+            "lowercase" => Some(RenameRule::Lower),
+            "UPPERCASE" => Some(RenameRule::Upper),
+            "PascalCase" => Some(RenameRule::Pascal),
+            "camelCase" => Some(RenameRule::Camel),
+            "snake_case" => Some(RenameRule::Snake),
+            "SCREAMING_SNAKE_CASE" => Some(RenameRule::ScreamingSnake),
+            "kebab-case" => Some(RenameRule::Kebab),
+            "SCREAMING-KEBAB-CASE" => Some(RenameRule::ScreamingKebab),
+            _ => None,
+        }
+    }
+
+    // same rules serde uses for variants, which are expected to be PascalCase
+    pub fn apply_to_variant(&self, variant: &str) -> String {
+        match self {
+            RenameRule::Pascal => variant.to_owned(),
+            RenameRule::Lower => variant.to_ascii_lowercase(),
+            RenameRule::Upper => variant.to_ascii_uppercase(),
+            RenameRule::Camel => lowercase_first(variant),
+            RenameRule::Snake => {
+                let mut snake = String::new();
+                for (i, ch) in variant.char_indices() {
+                    if i > 0 && ch.is_uppercase() {
+                        snake.push('_');
+                    }
+                    snake.push(ch.to_ascii_lowercase());
+                }
+                snake
+            }
+            RenameRule::ScreamingSnake => RenameRule::Snake
+                .apply_to_variant(variant)
+                .to_ascii_uppercase(),
+            RenameRule::Kebab => RenameRule::Snake
+                .apply_to_variant(variant)
+                .replace('_', "-"),
+            RenameRule::ScreamingKebab => RenameRule::ScreamingSnake
+                .apply_to_variant(variant)
+                .replace('_', "-"),
+        }
+    }
+
+    // same rules serde uses for fields, which are expected to be snake_case
+    pub fn apply_to_field(&self, field: &str) -> String {
+        match self {
+            RenameRule::Lower | RenameRule::Snake => field.to_owned(),
+            RenameRule::Upper | RenameRule::ScreamingSnake => field.to_ascii_uppercase(),
+            RenameRule::Pascal => {
+                let mut pascal = String::new();
+                let mut capitalize = true;
+                for ch in field.chars() {
+                    if ch == '_' {
+                        capitalize = true;
+                    } else if capitalize {
+                        pascal.push(ch.to_ascii_uppercase());
+                        capitalize = false;
+                    } else {
+                        pascal.push(ch);
+                    }
+                }
+                pascal
+            }
+            RenameRule::Camel => lowercase_first(&RenameRule::Pascal.apply_to_field(field)),
+            RenameRule::Kebab => field.replace('_', "-"),
+            RenameRule::ScreamingKebab => field.to_ascii_uppercase().replace('_', "-"),
+        }
+    }
+}
+
+fn lowercase_first(name: &str) -> String {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_ascii_lowercase().to_string() + chars.as_str(),
+        None => String::new(),
+    }
+}
+
+/// The subset of `#[serde(...)]` that changes the JSON shape of a message.
+/// Container, variant and field attributes all land in the same struct; each
+/// position only looks at the options that apply to it.
+#[derive(Debug, Default, Clone)]
+pub struct SerdeAttrs {
+    pub rename: Option<String>,
+    pub rename_all: Option<RenameRule>,
+    pub rename_all_fields: Option<RenameRule>,
+    pub tag: Option<String>,
+    pub content: Option<String>,
+    pub untagged: bool,
+    pub transparent: bool,
+    pub skip: bool,
+    pub optional: bool,
+    pub flatten: bool,
+}
+
+pub enum Tagging {
+    External,
+    Internal { tag: String },
+    Adjacent { tag: String, content: String },
+    Untagged,
+}
+
+impl SerdeAttrs {
+    pub fn from_attrs(attrs: &[Attribute]) -> Self {
+        let mut serde_attrs = SerdeAttrs::default();
+
+        for attr in attrs {
+            if !attr.path().is_ident("serde") {
+                continue;
+            }
+
+            // malformed attributes are reported by serde_derive itself, so
+            // anything we can't read here is simply ignored
+            let _ = attr.parse_nested_meta(|meta| {
+                let Some(ident) = meta.path.get_ident() else {
+                    return skip_meta_value(&meta);
+                };
+
+                match ident.to_string().as_str() {
+                    "rename" => serde_attrs.rename = parse_serialize_name(&meta)?,
+                    "rename_all" => {
+                        serde_attrs.rename_all = parse_serialize_name(&meta)?
+                            .and_then(|rule| RenameRule::from_str(&rule))
+                    }
+                    "rename_all_fields" => {
+                        serde_attrs.rename_all_fields = parse_serialize_name(&meta)?
+                            .and_then(|rule| RenameRule::from_str(&rule))
+                    }
+                    "tag" => serde_attrs.tag = Some(meta.value()?.parse::<LitStr>()?.value()),
+                    "content" => {
+                        serde_attrs.content = Some(meta.value()?.parse::<LitStr>()?.value())
+                    }
+                    "untagged" => serde_attrs.untagged = true,
+                    "transparent" => serde_attrs.transparent = true,
+                    "skip" => serde_attrs.skip = true,
+                    "flatten" => serde_attrs.flatten = true,
+                    // the field may be missing on one side of the wire
+                    "default"
+                    | "skip_serializing"
+                    | "skip_deserializing"
+                    | "skip_serializing_if" => {
+                        serde_attrs.optional = true;
+                        skip_meta_value(&meta)?;
+                    }
+                    _ => skip_meta_value(&meta)?,
+                }
+
+                Ok(())
+            });
+        }
+
+        serde_attrs
+    }
+
+    pub fn tagging(&self) -> Tagging {
+        match (&self.tag, &self.content, self.untagged) {
+            (_, _, true) => Tagging::Untagged,
+            (Some(tag), Some(content), _) => Tagging::Adjacent {
+                tag: tag.clone(),
+                content: content.clone(),
+            },
+            (Some(tag), None, _) => Tagging::Internal { tag: tag.clone() },
+            _ => Tagging::External,
+        }
+    }
+}
+
+// `rename = "a"` or `rename(serialize = "a", deserialize = "b")`; only the
+// serialized name counts since that is what the client receives, a
+// deserialize-only rename leaves it as it is
+fn parse_serialize_name(meta: &syn::meta::ParseNestedMeta) -> syn::Result<Option<String>> {
+    if meta.input.peek(syn::Token![=]) {
+        return Ok(Some(meta.value()?.parse::<LitStr>()?.value()));
+    }
+
+    let mut name = None;
+    meta.parse_nested_meta(|nested| {
+        let value = nested.value()?.parse::<LitStr>()?.value();
+        if nested.path.is_ident("serialize") {
+            name = Some(value);
+        }
+        Ok(())
+    })?;
+
+    Ok(name)
+}
+
+fn skip_meta_value(meta: &syn::meta::ParseNestedMeta) -> syn::Result<()> {
+    if meta.input.peek(syn::Token![=]) {
+        meta.value()?.parse::<syn::Expr>()?;
+    } else if meta.input.peek(syn::token::Paren) {
+        meta.parse_nested_meta(|nested| skip_meta_value(&nested))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attrs(item: syn::DeriveInput) -> SerdeAttrs {
+        SerdeAttrs::from_attrs(&item.attrs)
+    }
+
+    #[test]
+    fn camel_cases_any_first_char() {
+        assert_eq!(RenameRule::Camel.apply_to_variant("NameTaken"), "nameTaken");
+        assert_eq!(RenameRule::Camel.apply_to_variant("Écrit"), "Écrit");
+        assert_eq!(RenameRule::Camel.apply_to_variant(""), "");
+        assert_eq!(RenameRule::Camel.apply_to_field("min_length"), "minLength");
+        assert_eq!(RenameRule::Camel.apply_to_field("été_vu"), "étéVu");
+        assert_eq!(RenameRule::Camel.apply_to_field(""), "");
+    }
+
+    #[test]
+    fn takes_the_serialized_name_only() {
+        let renamed = attrs(syn::parse_quote! {
+            #[serde(rename = "a")]
+            struct A;
+        });
+        assert_eq!(renamed.rename.as_deref(), Some("a"));
+
+        let renamed = attrs(syn::parse_quote! {
+            #[serde(rename(deserialize = "d", serialize = "s"))]
+            struct A;
+        });
+        assert_eq!(renamed.rename.as_deref(), Some("s"));
+
+        let renamed = attrs(syn::parse_quote! {
+            #[serde(rename(deserialize = "dd"))]
+            struct A;
+        });
+        assert_eq!(renamed.rename, None);
+
+        let renamed = attrs(syn::parse_quote! {
+            #[serde(rename_all(deserialize = "snake_case", serialize = "camelCase"))]
+            struct A;
+        });
+        assert!(matches!(renamed.rename_all, Some(RenameRule::Camel)));
+    }
+
+    #[test]
+    fn reads_field_options() {
+        let skipped = attrs(syn::parse_quote! {
+            #[serde(skip)]
+            struct A;
+        });
+        assert!(skipped.skip && !skipped.optional);
+
+        let defaulted = attrs(syn::parse_quote! {
+            #[serde(default = "default_name")]
+            struct A;
+        });
+        assert!(defaulted.optional && !defaulted.skip);
+
+        let flattened = attrs(syn::parse_quote! {
+            #[serde(flatten, skip_serializing_if = "Option::is_none")]
+            struct A;
+        });
+        assert!(flattened.flatten && flattened.optional);
+    }
+
+    #[test]
+    fn tells_the_tagging_modes_apart() {
+        let tagging = |item| attrs(item).tagging();
+
+        assert!(matches!(
+            tagging(syn::parse_quote! {
+                enum A {}
+            }),
+            Tagging::External
+        ));
+        assert!(matches!(
+            tagging(syn::parse_quote! {
+                #[serde(tag = "type")]
+                enum A {}
+            }),
+            Tagging::Internal { tag } if tag == "type"
+        ));
+        assert!(matches!(
+            tagging(syn::parse_quote! {
+                #[serde(tag = "t", content = "c")]
+                enum A {}
+            }),
+            Tagging::Adjacent { tag, content } if tag == "t" && content == "c"
+        ));
+        assert!(matches!(
+            tagging(syn::parse_quote! {
+                #[serde(untagged)]
+                enum A {}
+            }),
+            Tagging::Untagged
+        ));
+    }
+}
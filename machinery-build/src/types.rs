@@ -0,0 +1,377 @@
+use std::fmt;
+
+use crate::imports::Imports;
+
+/// TypeScript view of a Rust type, shaped after how serde_json encodes it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TsType {
+    String,
+    Number,
+    Boolean,
+    BigInt,
+    Null,
+    Void,
+    Unknown,
+    Array(Box<TsType>),
+    Tuple(Vec<TsType>),
+    Record(Box<TsType>, Box<TsType>),
+    Nullable(Box<TsType>),
+    Result(Box<TsType>, Box<TsType>),
+    TypeParam(String),
+    Reference {
+        name: String,
+        args: Vec<TsType>,
+    },
+    Mapped {
+        ts_type: String,
+        import: Option<String>,
+        args: Vec<TsType>,
+    },
+    Unsupported(String),
+}
+
+impl TsType {
+    // unions need parentheses when they are the element of an array
+    fn is_union(&self) -> bool {
+        matches!(self, TsType::Nullable(_) | TsType::Result(_, _))
+    }
+
+    /// Calls `f` for this type and every type nested in it.
+    pub fn walk(&self, f: &mut impl FnMut(&TsType)) {
+        f(self);
+
+        match self {
+            TsType::Array(inner) | TsType::Nullable(inner) => inner.walk(f),
+            TsType::Record(a, b) | TsType::Result(a, b) => {
+                a.walk(f);
+                b.walk(f);
+            }
+            TsType::Tuple(items)
+            | TsType::Reference { args: items, .. }
+            | TsType::Mapped { args: items, .. } => items.iter().for_each(|item| item.walk(f)),
+            _ => {}
+        }
+    }
+}
+
+impl fmt::Display for TsType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TsType::String => write!(f, "string"),
+            TsType::Number => write!(f, "number"),
+            TsType::Boolean => write!(f, "boolean"),
+            TsType::BigInt => write!(f, "bigint"),
+            TsType::Null => write!(f, "null"),
+            TsType::Void => write!(f, "void"),
+            TsType::Unknown | TsType::Unsupported(_) => write!(f, "unknown"),
+            TsType::Array(inner) if inner.is_union() => write!(f, "({})[]", inner),
+            TsType::Array(inner) => write!(f, "{}[]", inner),
+            TsType::Tuple(items) => write!(f, "[{}]", join(items)),
+            TsType::Record(key, value) => write!(f, "Record<{}, {}>", key, value),
+            TsType::Nullable(inner) => write!(f, "{} | null", inner),
+            TsType::Result(ok, err) => write!(f, "{{ Ok: {} }} | {{ Err: {} }}", ok, err),
+            TsType::TypeParam(name) => write!(f, "{}", name),
+            TsType::Reference { name, args } if args.is_empty() => write!(f, "{}", name),
+            TsType::Reference { name, args } => write!(f, "{}<{}>", name, join(args)),
+            TsType::Mapped { ts_type, .. } => write!(f, "{}", ts_type),
+        }
+    }
+}
+
+fn join(types: &[TsType]) -> String {
+    types
+        .iter()
+        .map(|ty| ty.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// How 64 and 128 bit integers are represented in the generated client.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Int64Encoding {
+    /// Plain JSON numbers, which lose precision above 2^53.
+    #[default]
+    Number,
+    /// `bigint` in TypeScript, sent as decimal strings and revived by the
+    /// generated client.
+    BigInt,
+    /// Decimal strings on both sides.
+    String,
+}
+
+/// Maps a foreign Rust type to a TypeScript type, eg. `chrono::DateTime` to
+/// `string`. The Rust path is resolved through the `use` declarations of its
+/// module and matched on its trailing segments, so `chrono::DateTime` also
+/// matches a `DateTime` that was glob imported from `chrono`, and `*`
+/// matches any segment. Types of the crate itself are never mapped. `$0`,
+/// `$1`, ... in the TypeScript type are replaced with the mapped generic
+/// arguments of the Rust type.
+#[derive(Debug, Clone)]
+pub struct TypeMapping {
+    pub rust_path: String,
+    pub ts_type: String,
+    pub import: Option<String>,
+}
+
+impl TypeMapping {
+    pub fn new(rust_path: &str, ts_type: &str) -> Self {
+        TypeMapping {
+            rust_path: rust_path.to_owned(),
+            ts_type: ts_type.to_owned(),
+            import: None,
+        }
+    }
+
+    /// An import statement added to the client when the mapping is used,
+    /// eg. `import { Decimal } from "decimal.js";`
+    pub fn with_import(mut self, import: &str) -> Self {
+        self.import = Some(import.to_owned());
+
+        self
+    }
+
+    fn matches(&self, path: &syn::Path, imports: &Imports) -> bool {
+        let candidates = imports.resolve(path);
+        if path.leading_colon.is_none()
+            && candidates
+                .iter()
+                .any(|candidate| imports.is_crate_path(candidate))
+        {
+            return false;
+        }
+
+        candidates.iter().any(|candidate| {
+            let pattern = self.rust_path.trim_start_matches("::").rsplit("::");
+
+            pattern
+                .zip(candidate.iter().rev())
+                .all(|(pattern, segment)| pattern == "*" || pattern == segment)
+        })
+    }
+
+    // whether `$index` is in the TypeScript type, `$1` isn't part of `$10`
+    fn uses_arg(&self, index: usize) -> bool {
+        let placeholder = format!("${}", index);
+
+        self.ts_type.match_indices(&placeholder).any(|(at, _)| {
+            !self.ts_type[at + placeholder.len()..].starts_with(|c: char| c.is_ascii_digit())
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TypeMapper {
+    pub int64_encoding: Int64Encoding,
+    pub mappings: Vec<TypeMapping>,
+}
+
+fn generic_args(segment: &syn::PathSegment) -> Vec<&syn::Type> {
+    match &segment.arguments {
+        syn::PathArguments::AngleBracketed(args) => args
+            .args
+            .iter()
+            .filter_map(|arg| match arg {
+                syn::GenericArgument::Type(ty) => Some(ty),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+impl TypeMapper {
+    /// Maps a Rust type to its TypeScript counterpart. `type_params` are the
+    /// generic parameters in scope, which are passed through by name, and
+    /// `imports` the ones of the module the type is written in. Types that
+    /// have no JSON representation come back as `TsType::Unsupported`.
+    pub fn map(&self, ty: &syn::Type, type_params: &[String], imports: &Imports) -> TsType {
+        match ty {
+            syn::Type::Reference(reference) => self.map(&reference.elem, type_params, imports),
+            syn::Type::Paren(paren) => self.map(&paren.elem, type_params, imports),
+            syn::Type::Group(group) => self.map(&group.elem, type_params, imports),
+            syn::Type::Slice(slice) => {
+                TsType::Array(Box::new(self.map(&slice.elem, type_params, imports)))
+            }
+            syn::Type::Array(array) => {
+                TsType::Array(Box::new(self.map(&array.elem, type_params, imports)))
+            }
+            syn::Type::Tuple(tuple) if tuple.elems.is_empty() => TsType::Null,
+            syn::Type::Tuple(tuple) => TsType::Tuple(
+                tuple
+                    .elems
+                    .iter()
+                    .map(|elem| self.map(elem, type_params, imports))
+                    .collect(),
+            ),
+            syn::Type::Path(path) if path.qself.is_none() => {
+                self.map_path(&path.path, type_params, imports)
+            }
+            _ => TsType::Unsupported(quote::quote!(#ty).to_string()),
+        }
+    }
+
+    fn int64(&self) -> TsType {
+        match self.int64_encoding {
+            Int64Encoding::Number => TsType::Number,
+            Int64Encoding::BigInt => TsType::BigInt,
+            Int64Encoding::String => TsType::String,
+        }
+    }
+
+    fn map_path(&self, path: &syn::Path, type_params: &[String], imports: &Imports) -> TsType {
+        let segment = path.segments.last().unwrap();
+        let name = segment.ident.to_string();
+        let args = generic_args(segment);
+        let map_arg = |index: usize| match args.get(index) {
+            Some(arg) => self.map(arg, type_params, imports),
+            None => TsType::Unknown,
+        };
+
+        if path.segments.len() == 1 && args.is_empty() && type_params.contains(&name) {
+            return TsType::TypeParam(name);
+        }
+
+        if let Some(mapping) = self
+            .mappings
+            .iter()
+            .find(|mapping| mapping.matches(path, imports))
+        {
+            let args = (0..args.len()).map(map_arg).collect::<Vec<_>>();
+            // replace from the last index so $1 doesn't clobber $10
+            let ts_type = args
+                .iter()
+                .enumerate()
+                .rev()
+                .fold(mapping.ts_type.clone(), |ts_type, (index, arg)| {
+                    ts_type.replace(&format!("${}", index), &arg.to_string())
+                });
+
+            // the arguments that aren't substituted don't end up in the client
+            return TsType::Mapped {
+                ts_type,
+                import: mapping.import.clone(),
+                args: args
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(index, _)| mapping.uses_arg(*index))
+                    .map(|(_, arg)| arg)
+                    .collect(),
+            };
+        }
+
+        match name.as_str() {
+            "String" | "str" | "char" | "PathBuf" | "Path" => TsType::String,
+            "bool" => TsType::Boolean,
+            "u8" | "u16" | "u32" | "i8" | "i16" | "i32" | "f32" | "f64" => TsType::Number,
+            "NonZeroU8" | "NonZeroU16" | "NonZeroU32" | "NonZeroI8" | "NonZeroI16"
+            | "NonZeroI32" => TsType::Number,
+            "u64" | "u128" | "usize" | "i64" | "i128" | "isize" => self.int64(),
+            "NonZeroU64" | "NonZeroU128" | "NonZeroUsize" | "NonZeroI64" | "NonZeroI128"
+            | "NonZeroIsize" => self.int64(),
+            "Void" | "PhantomData" => TsType::Null,
+            "Option" => TsType::Nullable(Box::new(map_arg(0))),
+            "Vec" | "VecDeque" | "LinkedList" | "HashSet" | "BTreeSet" | "BinaryHeap"
+            | "IndexSet" => TsType::Array(Box::new(map_arg(0))),
+            // JSON object keys are strings no matter how the integers are encoded
+            "HashMap" | "BTreeMap" | "IndexMap" => match map_arg(0) {
+                TsType::BigInt => TsType::Record(Box::new(TsType::String), Box::new(map_arg(1))),
+                key => TsType::Record(Box::new(key), Box::new(map_arg(1))),
+            },
+            "Box" | "Arc" | "Rc" | "Cow" | "Cell" | "RefCell" => map_arg(0),
+            // anyhow style results only carry the error on the Rust side
+            "Result" if args.len() == 1 => {
+                TsType::Result(Box::new(map_arg(0)), Box::new(TsType::String))
+            }
+            "Result" => TsType::Result(Box::new(map_arg(0)), Box::new(map_arg(1))),
+            _ => TsType::Reference {
+                name,
+                args: (0..args.len()).map(map_arg).collect(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapper() -> TypeMapper {
+        TypeMapper {
+            int64_encoding: Int64Encoding::Number,
+            mappings: vec![
+                TypeMapping::new("chrono::DateTime", "string"),
+                TypeMapping::new("serde_json::Value", "unknown"),
+                TypeMapping::new("indexmap::IndexMap", "Map<$0, $1>"),
+            ],
+        }
+    }
+
+    fn map(ty: syn::Type, imports: syn::File) -> TsType {
+        mapper().map(&ty, &[], &Imports::from_items(&imports.items))
+    }
+
+    #[test]
+    fn resolves_mapped_types_through_the_imports() {
+        let imports = syn::parse_quote! {
+            use chrono::*;
+            use serde_json::Value as Json;
+        };
+        assert_eq!(
+            map(syn::parse_quote!(DateTime<Utc>), imports).to_string(),
+            "string"
+        );
+
+        let imports = syn::parse_quote! {
+            use serde_json::Value as Json;
+        };
+        assert_eq!(map(syn::parse_quote!(Json), imports).to_string(), "unknown");
+        let imports = syn::parse_quote! {};
+        assert_eq!(
+            map(syn::parse_quote!(serde_json::Value), imports).to_string(),
+            "unknown"
+        );
+    }
+
+    #[test]
+    fn leaves_types_of_the_crate_alone() {
+        let local = syn::parse_quote! {
+            struct Value;
+        };
+        let imported = syn::parse_quote! {
+            use crate::api::Value;
+        };
+        let glob_imported = syn::parse_quote! {
+            use crate::api::*;
+            use serde_json::*;
+        };
+
+        for imports in [local, imported, glob_imported] {
+            assert_eq!(
+                map(syn::parse_quote!(Value), imports),
+                TsType::Reference {
+                    name: "Value".to_owned(),
+                    args: vec![],
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn keeps_the_substituted_arguments_only() {
+        let imports = syn::parse_quote! {};
+        let TsType::Mapped { args, .. } = map(syn::parse_quote!(chrono::DateTime<Utc>), imports)
+        else {
+            panic!("not mapped");
+        };
+        assert!(args.is_empty());
+
+        let imports = syn::parse_quote! {};
+        let TsType::Mapped { ts_type, args, .. } =
+            map(syn::parse_quote!(indexmap::IndexMap<String, u32>), imports)
+        else {
+            panic!("not mapped");
+        };
+        assert_eq!(ts_type, "Map<string, number>");
+        assert_eq!(args, [TsType::String, TsType::Number]);
+    }
+}
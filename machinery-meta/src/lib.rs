@@ -1,14 +1,69 @@
 use proc_macro::TokenStream;
 use syn::parse_macro_input;
 
+// the build pipeline can't resolve paths with generic arguments, a guard it
+// doesn't see would never run
+fn parse_option_path(input: syn::parse::ParseStream) -> syn::Result<syn::Path> {
+    let path = input.parse::<syn::Path>()?;
+    if let Some(segment) = path
+        .segments
+        .iter()
+        .find(|segment| !segment.arguments.is_empty())
+    {
+        return Err(syn::Error::new_spanned(
+            &segment.arguments,
+            "generic arguments are not supported here",
+        ));
+    }
+
+    Ok(path)
+}
+
 #[proc_macro_attribute]
-pub fn service(_attr: TokenStream, item: TokenStream) -> TokenStream {
+pub fn service(attr: TokenStream, item: TokenStream) -> TokenStream {
+    // interceptors and guards are called by the generated handlers, they are
+    // only checked here
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("before") || meta.path.is_ident("after") {
+            parse_option_path(meta.value()?)?;
+            Ok(())
+        } else if meta.path.is_ident("guard") {
+            let value = meta.value()?;
+            if value.peek(syn::LitStr) {
+                value.parse::<syn::LitStr>()?;
+            } else {
+                parse_option_path(value)?;
+            }
+            Ok(())
+        } else {
+            Err(meta.error("expected `before`, `after` or `guard`"))
+        }
+    });
+    parse_macro_input!(attr with parser);
+
     let item = parse_macro_input!(item as syn::ItemFn);
     let block = item.block;
     let vis = item.vis;
     let mut sig = item.sig;
+    // `#[inject]` only tells the build pipeline what to resolve
+    for input in sig.inputs.iter_mut() {
+        if let syn::FnArg::Typed(pat) = input {
+            pat.attrs.retain(|attr| {
+                attr.path()
+                    .segments
+                    .last()
+                    .is_none_or(|segment| segment.ident != "inject")
+            });
+        }
+    }
+
+    // methods keep their receiver first
+    let index = match sig.inputs.first() {
+        Some(syn::FnArg::Receiver(_)) => 1,
+        _ => 0,
+    };
     sig.inputs.insert(
-        0,
+        index,
         syn::parse_quote!(
             #[allow(unused_variables)]
             ctx: &machinery::context::Context
@@ -26,17 +81,18 @@ pub fn service(_attr: TokenStream, item: TokenStream) -> TokenStream {
 pub fn message(_attr: TokenStream, item: TokenStream) -> TokenStream {
     let item = parse_macro_input!(item as syn::Item);
 
-    let name = match &item {
-        syn::Item::Struct(item) => &item.ident,
-        syn::Item::Enum(item) => &item.ident,
+    let (name, generics) = match &item {
+        syn::Item::Struct(item) => (&item.ident, &item.generics),
+        syn::Item::Enum(item) => (&item.ident, &item.generics),
         _ => panic!("Only structs and enums can be messages"),
     };
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
 
     let item = quote::quote! {
         #[derive(machinery::Serialize, machinery::Deserialize, Debug)]
         #item
-        impl From<#name> for machinery::Result<#name> {
-            fn from(src: #name) -> machinery::Result<#name> {
+        impl #impl_generics From<#name #ty_generics> for machinery::Result<#name #ty_generics> #where_clause {
+            fn from(src: #name #ty_generics) -> machinery::Result<#name #ty_generics> {
                 Ok(src)
             }
         }
@@ -58,6 +114,30 @@ pub fn injectable(_attr: TokenStream, item: TokenStream) -> TokenStream {
     item.into()
 }
 
+#[proc_macro_attribute]
+pub fn guard(_attr: TokenStream, item: TokenStream) -> TokenStream {
+    let item = parse_macro_input!(item as syn::ItemImpl);
+
+    let item = quote::quote! {
+        #[machinery::__internal_async::async_trait]
+        #item
+    };
+
+    item.into()
+}
+
+#[proc_macro_attribute]
+pub fn interceptor(_attr: TokenStream, item: TokenStream) -> TokenStream {
+    let item = parse_macro_input!(item as syn::ItemImpl);
+
+    let item = quote::quote! {
+        #[machinery::__internal_async::async_trait]
+        #item
+    };
+
+    item.into()
+}
+
 #[proc_macro]
 pub fn load_services(_item: TokenStream) -> TokenStream {
     let item = quote::quote! {
@@ -1,6 +1,187 @@
-use axum::http::HeaderMap;
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    fmt,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use anyhow::{anyhow, Result};
+use axum::{
+    extract::ConnectInfo,
+    http::{request::Parts, Extensions, HeaderMap, HeaderName, HeaderValue, Method, Uri},
+};
+use serde::de::DeserializeOwned;
+
+const REQUEST_ID_HEADER: &str = "x-request-id";
 
 #[derive(Debug)]
 pub struct Context {
     pub headers: HeaderMap,
+    pub method: Method,
+    pub uri: Uri,
+    /// The address of the client, `None` when it isn't known.
+    pub remote_addr: Option<IpAddr>,
+    /// The `x-request-id` the caller sent, or a new one.
+    pub request_id: String,
+    pub received_at: SystemTime,
+    extensions: Mutex<Extensions>,
+    state: Arc<AppState>,
+    // the injectables built for this request, as `Arc`s
+    injected: Mutex<HashMap<TypeId, Box<dyn Any + Send + Sync>>>,
+}
+
+/// The values given to `Machinery::with_state`, by type.
+#[derive(Clone, Default)]
+pub(crate) struct AppState(HashMap<TypeId, Arc<dyn Any + Send + Sync>>);
+
+impl AppState {
+    pub(crate) fn insert<T: Send + Sync + 'static>(&mut self, state: T) {
+        self.0.insert(TypeId::of::<T>(), Arc::new(state));
+    }
+}
+
+impl fmt::Debug for AppState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AppState").finish_non_exhaustive()
+    }
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Context {
+            headers: HeaderMap::new(),
+            method: Method::default(),
+            uri: Uri::default(),
+            remote_addr: None,
+            request_id: new_request_id(),
+            received_at: SystemTime::now(),
+            extensions: Mutex::default(),
+            state: Arc::default(),
+            injected: Mutex::default(),
+        }
+    }
+}
+
+impl Context {
+    /// A context that doesn't come from an HTTP request, for calling services
+    /// from queues, CLIs or tests.
+    pub fn new() -> Self {
+        Context::default()
+    }
+
+    /// The connection address comes from `ConnectInfo`, with `trusted_proxies`
+    /// set the client is taken from `x-forwarded-for` instead. The request
+    /// extensions, eg. added by layers, are kept.
+    pub(crate) fn from_request(parts: Parts, trusted_proxies: usize) -> Self {
+        let peer_addr = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        let remote_addr = match trusted_proxies {
+            0 => peer_addr,
+            _ => forwarded_for(&parts.headers, trusted_proxies).or(peer_addr),
+        };
+        let request_id = parts
+            .headers
+            .get(REQUEST_ID_HEADER)
+            .and_then(|id| id.to_str().ok())
+            .map(|id| id.to_owned())
+            .unwrap_or_else(new_request_id);
+
+        Context {
+            headers: parts.headers,
+            method: parts.method,
+            uri: parts.uri,
+            remote_addr,
+            request_id,
+            received_at: SystemTime::now(),
+            extensions: Mutex::new(parts.extensions),
+            state: Arc::default(),
+            injected: Mutex::default(),
+        }
+    }
+
+    pub(crate) fn set_state(&mut self, state: Arc<AppState>) {
+        self.state = state;
+    }
+
+    pub(crate) fn injected<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        let injected = self.injected.lock().unwrap();
+        injected.get(&TypeId::of::<T>())?.downcast_ref().cloned()
+    }
+
+    // injectables of the same request racing each other are both built, the
+    // last one is kept
+    pub(crate) fn cache_injected<T: Send + Sync + 'static>(&self, value: Arc<T>) {
+        let mut injected = self.injected.lock().unwrap();
+        injected.insert(TypeId::of::<T>(), Box::new(value));
+    }
+
+    /// Fails when `name` or `value` aren't valid in an HTTP header.
+    pub fn with_header<K, V>(mut self, name: K, value: V) -> Result<Self>
+    where
+        HeaderName: TryFrom<K>,
+        <HeaderName as TryFrom<K>>::Error: std::error::Error + Send + Sync + 'static,
+        HeaderValue: TryFrom<V>,
+        <HeaderValue as TryFrom<V>>::Error: std::error::Error + Send + Sync + 'static,
+    {
+        let name = HeaderName::try_from(name)?;
+        let value = HeaderValue::try_from(value)?;
+        self.headers.insert(name, value);
+
+        Ok(self)
+    }
+
+    pub fn path(&self) -> &str {
+        self.uri.path()
+    }
+
+    /// The value of type `T` given to `Machinery::with_state`.
+    pub fn state<T: Send + Sync + 'static>(&self) -> Result<&T> {
+        self.state
+            .0
+            .get(&TypeId::of::<T>())
+            .and_then(|state| state.downcast_ref())
+            .ok_or_else(|| anyhow!("No state of type {}", std::any::type_name::<T>()))
+    }
+
+    /// A value of this request by type. They start out as the extensions of
+    /// the HTTP request, eg. added by tower layers, and services and
+    /// injectables can add their own with `insert_extension`.
+    pub fn extension<T: Clone + Send + Sync + 'static>(&self) -> Option<T> {
+        self.extensions.lock().unwrap().get::<T>().cloned()
+    }
+
+    /// Adds a value to the request, returning the one of the same type it
+    /// replaces.
+    pub fn insert_extension<T: Send + Sync + 'static>(&self, value: T) -> Option<T> {
+        self.extensions.lock().unwrap().insert(value)
+    }
+
+    /// Deserializes the query string, eg. into a struct or a `HashMap`.
+    pub fn query<T: DeserializeOwned>(&self) -> Result<T> {
+        Ok(serde_urlencoded::from_str(
+            self.uri.query().unwrap_or_default(),
+        )?)
+    }
+}
+
+fn new_request_id() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+// each proxy appends the address it got the request from, the one added by
+// the outermost trusted proxy is the client
+fn forwarded_for(headers: &HeaderMap, trusted_proxies: usize) -> Option<IpAddr> {
+    let addrs = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|addr| addr.trim())
+        .collect::<Vec<_>>();
+
+    addrs.iter().rev().nth(trusted_proxies - 1)?.parse().ok()
 }
@@ -0,0 +1,153 @@
+use axum::http::StatusCode;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::MachineryResponse;
+
+pub use anyhow::{anyhow, bail, Context, Error};
+
+/// An error with a machine readable code, for failures the client is
+/// expected to handle. It can be returned directly or wrapped with context:
+/// `bail!(ServiceError::new("not_found", "No such user"))`. It is answered
+/// with `400 Bad Request` unless another status is given.
+#[derive(Debug, Clone)]
+pub struct ServiceError {
+    pub code: String,
+    pub message: String,
+    pub details: Option<Value>,
+    pub status: StatusCode,
+}
+
+impl ServiceError {
+    pub fn new(code: &str, message: impl Into<String>) -> Self {
+        ServiceError {
+            code: code.to_owned(),
+            message: message.into(),
+            details: None,
+            status: StatusCode::BAD_REQUEST,
+        }
+    }
+
+    pub fn unauthorized(message: impl Into<String>) -> Self {
+        ServiceError::new("unauthorized", message).with_status(StatusCode::UNAUTHORIZED)
+    }
+
+    pub fn forbidden(message: impl Into<String>) -> Self {
+        ServiceError::new("forbidden", message).with_status(StatusCode::FORBIDDEN)
+    }
+
+    pub fn with_status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+
+        self
+    }
+
+    pub fn with_details(mut self, details: impl Serialize) -> Self {
+        self.details = serde_json::to_value(details).ok();
+
+        self
+    }
+}
+
+impl std::fmt::Display for ServiceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ServiceError {}
+
+/// The `error` member of a failed response.
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorResponse {
+    pub message: String,
+    pub code: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<Value>,
+    // the service that returned the typed error in `details`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service: Option<String>,
+    // the anyhow context chain, only sent by debug builds
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub chain: Vec<String>,
+    // where the 64 bit integers of `details` are, for bigint clients
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bigints: Option<Value>,
+    #[serde(skip)]
+    pub status: StatusCode,
+}
+
+// statuses of the codes used by machinery itself
+fn default_status(code: &str) -> StatusCode {
+    match code {
+        "bad_request" | "bad_input" | "service_error" => StatusCode::BAD_REQUEST,
+        "unauthorized" => StatusCode::UNAUTHORIZED,
+        "forbidden" => StatusCode::FORBIDDEN,
+        "unknown_function" => StatusCode::NOT_FOUND,
+        "payload_too_large" => StatusCode::PAYLOAD_TOO_LARGE,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+impl ErrorResponse {
+    pub fn new(code: &str, message: impl Into<String>) -> Self {
+        ErrorResponse {
+            message: message.into(),
+            code: code.to_owned(),
+            details: None,
+            service: None,
+            chain: Vec::new(),
+            bigints: None,
+            status: default_status(code),
+        }
+    }
+
+    /// Takes the code and details from the first `ServiceError` in the chain,
+    /// `default_code` is used when there is none.
+    pub fn from_error(err: impl Into<Error>, default_code: &str) -> Self {
+        let err = err.into();
+        let service_error = err
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<ServiceError>());
+
+        let mut response = ErrorResponse::new(default_code, err.to_string());
+        if let Some(service_error) = service_error {
+            response.code = service_error.code.clone();
+            response.details = service_error.details.clone();
+            response.status = service_error.status;
+        }
+
+        if cfg!(debug_assertions) {
+            response.chain = err.chain().skip(1).map(|cause| cause.to_string()).collect();
+        }
+
+        response
+    }
+
+    /// A typed error returned by a service, `details` is its serialized form.
+    /// The name of the service lets clients tell the error types apart.
+    pub fn service_error(service: &str, details: &str, bigints: Option<&str>) -> Self {
+        let mut response = ErrorResponse::new("service_error", "Service error");
+        response.details = serde_json::from_str(details).ok();
+        response.service = Some(service.to_owned());
+        response.bigints = bigints.and_then(|bigints| serde_json::from_str(bigints).ok());
+
+        response
+    }
+
+    pub fn to_json(&self) -> String {
+        #[derive(Serialize)]
+        struct Envelope<'a> {
+            error: &'a ErrorResponse,
+        }
+
+        serde_json::to_string(&Envelope { error: self }).unwrap()
+    }
+
+    pub fn into_response(self) -> MachineryResponse {
+        MachineryResponse {
+            status: self.status,
+            body: self.to_json(),
+        }
+    }
+}
@@ -0,0 +1,30 @@
+use anyhow::Result;
+
+use crate::context::Context;
+
+/// Allows or denies the calls of `#[machinery::service(guard = AdminOnly)]`,
+/// a call that isn't allowed is answered with the error, or 403 for errors
+/// that aren't a `ServiceError`. Guards run before the input is decoded.
+#[async_trait::async_trait]
+pub trait Guard {
+    async fn check(ctx: &Context) -> Result<()>;
+}
+
+/// Checks the permissions of `#[machinery::service(guard = "admin")]`, a crate
+/// has a single type implementing it.
+#[async_trait::async_trait]
+pub trait Permissions {
+    async fn check(ctx: &Context, permission: &str) -> Result<()>;
+}
+
+pub mod __internal {
+    use anyhow::{anyhow, Result};
+
+    // permissions are denied when nothing checks them
+    pub fn unchecked(permission: &str) -> Result<()> {
+        Err(anyhow!(
+            "No type implements Permissions to check the `{}` permission",
+            permission
+        ))
+    }
+}
@@ -2,28 +2,61 @@ use anyhow::Result;
 
 use crate::context::Context;
 
+/// Like `Injectable`, for values that take async work to build.
 #[async_trait::async_trait]
 pub trait AsyncInjectable {
     async fn inject(ctx: &Context) -> Result<Box<Self>>;
 }
 
+/// A value services get from the request instead of the client. It is built
+/// once per request and shared: `inject!` and `Arc<T>` arguments get the same
+/// `Arc`, while `self`, `&mut self` and arguments taken by value get a value
+/// of their own. An implementation can inject the injectables it depends on.
 pub trait Injectable {
     fn inject(ctx: &Context) -> Result<Box<Self>>;
 }
 
 pub mod __internal {
+    use std::sync::Arc;
+
     use anyhow::Result;
 
     use crate::context::Context;
 
     use super::{AsyncInjectable, Injectable};
 
-    pub async fn inject_async<T: AsyncInjectable>(ctx: &Context) -> Result<T> {
+    pub async fn inject_async<T>(ctx: &Context) -> Result<Arc<T>>
+    where
+        T: AsyncInjectable + Send + Sync + 'static,
+    {
+        if let Some(injected) = ctx.injected::<T>() {
+            return Ok(injected);
+        }
+
+        let injected: Arc<T> = Arc::from(T::inject(ctx).await?);
+        ctx.cache_injected(injected.clone());
+        Ok(injected)
+    }
+
+    pub fn inject<T>(ctx: &Context) -> Result<Arc<T>>
+    where
+        T: Injectable + Send + Sync + 'static,
+    {
+        if let Some(injected) = ctx.injected::<T>() {
+            return Ok(injected);
+        }
+
+        let injected: Arc<T> = Arc::from(T::inject(ctx)?);
+        ctx.cache_injected(injected.clone());
+        Ok(injected)
+    }
+
+    pub async fn build_async<T: AsyncInjectable>(ctx: &Context) -> Result<T> {
         let boxed = T::inject(ctx).await?;
         Ok(*boxed)
     }
 
-    pub fn inject<T: Injectable>(ctx: &Context) -> Result<T> {
+    pub fn build<T: Injectable>(ctx: &Context) -> Result<T> {
         let boxed = T::inject(ctx)?;
         Ok(*boxed)
     }
@@ -0,0 +1,984 @@
+//! JSON encoding that carries 64 and 128 bit integers as decimal strings, so
+//! they survive clients that only have doubles. Both directions go through
+//! serde adapters around `serde_json`, which keeps the encoding type driven:
+//! only values serialized as `u64`/`i64`/`u128`/`i128` (and `usize`/`isize`)
+//! are affected.
+
+use std::cell::RefCell;
+use std::fmt;
+
+use serde::de::{self, DeserializeSeed, Visitor};
+use serde::ser::{self, Serialize};
+use serde_json::{value::RawValue, Value};
+
+type Path = Vec<Value>;
+
+#[derive(Default)]
+struct State {
+    path: Path,
+    paths: Vec<Path>,
+}
+
+/// Serializes `value` with every 64/128 bit integer written as a string.
+pub fn to_string<T: Serialize + ?Sized>(value: &T) -> serde_json::Result<String> {
+    to_string_with_paths(value).map(|(json, _)| json)
+}
+
+/// Like [`to_string`], also returning the JSON encoded list of paths (object
+/// keys and array indices) at which the stringified integers can be found.
+pub fn to_string_with_paths<T: Serialize + ?Sized>(
+    value: &T,
+) -> serde_json::Result<(String, String)> {
+    let state = RefCell::new(State::default());
+    let mut output = Vec::new();
+
+    value.serialize(Int64Serializer {
+        inner: &mut serde_json::Serializer::new(&mut output),
+        state: &state,
+    })?;
+
+    let paths = serde_json::to_string(&state.into_inner().paths)?;
+    // serde_json only ever writes valid UTF-8
+    let output = String::from_utf8(output).unwrap();
+
+    Ok((output, paths))
+}
+
+/// Deserializes `input`, accepting 64/128 bit integers as either numbers or
+/// decimal strings.
+///
+/// Values that serde buffers internally (untagged and internally tagged
+/// enums, flattened fields) still need to be plain numbers.
+pub fn from_str<'de, T: de::Deserialize<'de>>(input: &'de str) -> serde_json::Result<T> {
+    let mut deserializer = serde_json::Deserializer::from_str(input);
+    let value = T::deserialize(Int64Deserializer(&mut deserializer))?;
+    deserializer.end()?;
+
+    Ok(value)
+}
+
+struct Int64Serializer<'a, S> {
+    inner: S,
+    state: &'a RefCell<State>,
+}
+
+impl<'a, S: ser::Serializer> Int64Serializer<'a, S> {
+    fn serialize_integer(self, value: impl fmt::Display) -> Result<S::Ok, S::Error> {
+        let mut state = self.state.borrow_mut();
+        let path = state.path.clone();
+        state.paths.push(path);
+        drop(state);
+
+        self.inner.serialize_str(&value.to_string())
+    }
+}
+
+// a value nested at `segment`, serialized through the adapter
+struct Nested<'a, T: ?Sized> {
+    value: &'a T,
+    segment: Option<Value>,
+    state: &'a RefCell<State>,
+}
+
+impl<'a, T: Serialize + ?Sized> Serialize for Nested<'a, T> {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if let Some(segment) = &self.segment {
+            self.state.borrow_mut().path.push(segment.clone());
+        }
+
+        let result = self.value.serialize(Int64Serializer {
+            inner: serializer,
+            state: self.state,
+        });
+
+        if self.segment.is_some() {
+            self.state.borrow_mut().path.pop();
+        }
+
+        result
+    }
+}
+
+struct Compound<'a, C> {
+    inner: C,
+    state: &'a RefCell<State>,
+    index: u64,
+    key: Option<Value>,
+    // tuple and struct variants are nested under the variant name
+    variant: bool,
+}
+
+impl<'a, C> Compound<'a, C> {
+    fn new(inner: C, state: &'a RefCell<State>) -> Self {
+        Compound {
+            inner,
+            state,
+            index: 0,
+            key: None,
+            variant: false,
+        }
+    }
+
+    fn variant(inner: C, state: &'a RefCell<State>, variant: &str) -> Self {
+        state.borrow_mut().path.push(Value::from(variant));
+
+        Compound {
+            variant: true,
+            ..Compound::new(inner, state)
+        }
+    }
+
+    fn element<'b, T: ?Sized>(&mut self, value: &'b T) -> Nested<'b, T>
+    where
+        'a: 'b,
+    {
+        let segment = Value::from(self.index);
+        self.index += 1;
+
+        Nested {
+            value,
+            segment: Some(segment),
+            state: self.state,
+        }
+    }
+
+    fn field<'b, T: ?Sized>(&self, key: &str, value: &'b T) -> Nested<'b, T>
+    where
+        'a: 'b,
+    {
+        Nested {
+            value,
+            segment: Some(Value::from(key)),
+            state: self.state,
+        }
+    }
+
+    fn end_variant(&self) {
+        if self.variant {
+            self.state.borrow_mut().path.pop();
+        }
+    }
+}
+
+impl<'a, S: ser::Serializer> ser::Serializer for Int64Serializer<'a, S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+    type SerializeSeq = Compound<'a, S::SerializeSeq>;
+    type SerializeTuple = Compound<'a, S::SerializeTuple>;
+    type SerializeTupleStruct = Compound<'a, S::SerializeTupleStruct>;
+    type SerializeTupleVariant = Compound<'a, S::SerializeTupleVariant>;
+    type SerializeMap = Compound<'a, S::SerializeMap>;
+    type SerializeStruct = Compound<'a, S::SerializeStruct>;
+    type SerializeStructVariant = Compound<'a, S::SerializeStructVariant>;
+
+    fn serialize_i64(self, v: i64) -> Result<S::Ok, S::Error> {
+        self.serialize_integer(v)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<S::Ok, S::Error> {
+        self.serialize_integer(v)
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<S::Ok, S::Error> {
+        self.serialize_integer(v)
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<S::Ok, S::Error> {
+        self.serialize_integer(v)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.inner.is_human_readable()
+    }
+
+    fn serialize_bool(self, v: bool) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_bool(v)
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_i8(v)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_i16(v)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_i32(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_u8(v)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_u16(v)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_u32(v)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_f32(v)
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_f64(v)
+    }
+
+    fn serialize_char(self, v: char) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_char(v)
+    }
+
+    fn serialize_str(self, v: &str) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_str(v)
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_bytes(v)
+    }
+
+    fn serialize_none(self) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_none()
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_some(&Nested {
+            value,
+            segment: None,
+            state: self.state,
+        })
+    }
+
+    fn serialize_unit(self) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_unit()
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_unit_struct(name)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+    ) -> Result<S::Ok, S::Error> {
+        self.inner
+            .serialize_unit_variant(name, variant_index, variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_newtype_struct(
+            name,
+            &Nested {
+                value,
+                segment: None,
+                state: self.state,
+            },
+        )
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_newtype_variant(
+            name,
+            variant_index,
+            variant,
+            &Nested {
+                value,
+                segment: Some(Value::from(variant)),
+                state: self.state,
+            },
+        )
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, S::Error> {
+        Ok(Compound::new(self.inner.serialize_seq(len)?, self.state))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, S::Error> {
+        Ok(Compound::new(self.inner.serialize_tuple(len)?, self.state))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, S::Error> {
+        Ok(Compound::new(
+            self.inner.serialize_tuple_struct(name, len)?,
+            self.state,
+        ))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, S::Error> {
+        Ok(Compound::variant(
+            self.inner
+                .serialize_tuple_variant(name, variant_index, variant, len)?,
+            self.state,
+            variant,
+        ))
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, S::Error> {
+        Ok(Compound::new(self.inner.serialize_map(len)?, self.state))
+    }
+
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, S::Error> {
+        Ok(Compound::new(
+            self.inner.serialize_struct(name, len)?,
+            self.state,
+        ))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStructVariant, S::Error> {
+        Ok(Compound::variant(
+            self.inner
+                .serialize_struct_variant(name, variant_index, variant, len)?,
+            self.state,
+            variant,
+        ))
+    }
+}
+
+impl<'a, C: ser::SerializeSeq> ser::SerializeSeq for Compound<'a, C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        let element = self.element(value);
+        self.inner.serialize_element(&element)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<'a, C: ser::SerializeTuple> ser::SerializeTuple for Compound<'a, C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        let element = self.element(value);
+        self.inner.serialize_element(&element)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<'a, C: ser::SerializeTupleStruct> ser::SerializeTupleStruct for Compound<'a, C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        let element = self.element(value);
+        self.inner.serialize_field(&element)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<'a, C: ser::SerializeTupleVariant> ser::SerializeTupleVariant for Compound<'a, C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        let element = self.element(value);
+        self.inner.serialize_field(&element)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.end_variant();
+        self.inner.end()
+    }
+}
+
+impl<'a, C: ser::SerializeMap> ser::SerializeMap for Compound<'a, C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), C::Error> {
+        // keys are always strings in JSON, so they are left alone and only
+        // remembered as the path of the value that follows
+        self.key = match serde_json::to_value(key) {
+            Ok(Value::String(key)) => Some(Value::from(key)),
+            Ok(key) => Some(Value::from(key.to_string())),
+            Err(_) => None,
+        };
+
+        self.inner.serialize_key(key)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        let value = Nested {
+            value,
+            segment: self.key.take(),
+            state: self.state,
+        };
+        self.inner.serialize_value(&value)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<'a, C: ser::SerializeStruct> ser::SerializeStruct for Compound<'a, C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), C::Error> {
+        let field = self.field(key, value);
+        self.inner.serialize_field(key, &field)
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), C::Error> {
+        self.inner.skip_field(key)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<'a, C: ser::SerializeStructVariant> ser::SerializeStructVariant for Compound<'a, C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), C::Error> {
+        let field = self.field(key, value);
+        self.inner.serialize_field(key, &field)
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), C::Error> {
+        self.inner.skip_field(key)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.end_variant();
+        self.inner.end()
+    }
+}
+
+struct Int64Deserializer<D>(D);
+
+// wraps every visitor, access and seed handed to serde so that nested values
+// are deserialized through the adapter as well
+struct Wrap<T>(T);
+
+macro_rules! forward_deserialize {
+    ($($method:ident($($arg:ident: $ty:ty),*)),* $(,)?) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value, D::Error> {
+                self.0.$method($($arg,)* Wrap(visitor))
+            }
+        )*
+    };
+}
+
+impl<'de, D: de::Deserializer<'de>> de::Deserializer<'de> for Int64Deserializer<D> {
+    type Error = D::Error;
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, D::Error> {
+        self.0.deserialize_any(IntegerVisitor(visitor))
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, D::Error> {
+        self.0.deserialize_any(IntegerVisitor(visitor))
+    }
+
+    fn deserialize_i128<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, D::Error> {
+        match raw_integer(self.0)? {
+            RawInteger::String(digits) => IntegerVisitor(visitor).visit_str(&digits),
+            RawInteger::Number(number) => match number.parse::<i128>() {
+                Ok(v) => visitor.visit_i128(v),
+                Err(_) => Err(de::Error::invalid_value(
+                    de::Unexpected::Other(&number),
+                    &visitor,
+                )),
+            },
+        }
+    }
+
+    fn deserialize_u128<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, D::Error> {
+        match raw_integer(self.0)? {
+            RawInteger::String(digits) => IntegerVisitor(visitor).visit_str(&digits),
+            RawInteger::Number(number) => match number.parse::<u128>() {
+                Ok(v) => visitor.visit_u128(v),
+                Err(_) => Err(de::Error::invalid_value(
+                    de::Unexpected::Other(&number),
+                    &visitor,
+                )),
+            },
+        }
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.0.is_human_readable()
+    }
+
+    forward_deserialize! {
+        deserialize_any(),
+        deserialize_bool(),
+        deserialize_i8(),
+        deserialize_i16(),
+        deserialize_i32(),
+        deserialize_u8(),
+        deserialize_u16(),
+        deserialize_u32(),
+        deserialize_f32(),
+        deserialize_f64(),
+        deserialize_char(),
+        deserialize_str(),
+        deserialize_string(),
+        deserialize_bytes(),
+        deserialize_byte_buf(),
+        deserialize_option(),
+        deserialize_unit(),
+        deserialize_unit_struct(name: &'static str),
+        deserialize_newtype_struct(name: &'static str),
+        deserialize_seq(),
+        deserialize_tuple(len: usize),
+        deserialize_tuple_struct(name: &'static str, len: usize),
+        deserialize_map(),
+        deserialize_struct(name: &'static str, fields: &'static [&'static str]),
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]),
+        deserialize_identifier(),
+        deserialize_ignored_any(),
+    }
+}
+
+enum RawInteger {
+    String(String),
+    Number(String),
+}
+
+// `deserialize_any` reads numbers past 64 bits as floats, 128 bit integers are
+// read from the raw JSON instead so that they keep every digit
+fn raw_integer<'de, D: de::Deserializer<'de>>(deserializer: D) -> Result<RawInteger, D::Error> {
+    let raw = <Box<RawValue> as de::Deserialize>::deserialize(deserializer)?;
+    if raw.get().starts_with('"') {
+        return serde_json::from_str(raw.get())
+            .map(RawInteger::String)
+            .map_err(de::Error::custom);
+    }
+
+    Ok(RawInteger::Number(raw.get().to_owned()))
+}
+
+struct IntegerVisitor<V>(V);
+
+impl<'de, V: Visitor<'de>> Visitor<'de> for IntegerVisitor<V> {
+    type Value = V::Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        self.0.expecting(formatter)
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<V::Value, E> {
+        self.0.visit_i64(v)
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<V::Value, E> {
+        self.0.visit_u64(v)
+    }
+
+    fn visit_i128<E: de::Error>(self, v: i128) -> Result<V::Value, E> {
+        self.0.visit_i128(v)
+    }
+
+    fn visit_u128<E: de::Error>(self, v: u128) -> Result<V::Value, E> {
+        self.0.visit_u128(v)
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<V::Value, E> {
+        self.0.visit_f64(v)
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<V::Value, E> {
+        if let Ok(v) = v.parse::<u64>() {
+            return self.0.visit_u64(v);
+        }
+        if let Ok(v) = v.parse::<i64>() {
+            return self.0.visit_i64(v);
+        }
+        if let Ok(v) = v.parse::<u128>() {
+            return self.0.visit_u128(v);
+        }
+        if let Ok(v) = v.parse::<i128>() {
+            return self.0.visit_i128(v);
+        }
+
+        Err(E::invalid_value(de::Unexpected::Str(v), &self))
+    }
+}
+
+impl<'de, V: Visitor<'de>> Visitor<'de> for Wrap<V> {
+    type Value = V::Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        self.0.expecting(formatter)
+    }
+
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<V::Value, E> {
+        self.0.visit_bool(v)
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<V::Value, E> {
+        self.0.visit_i64(v)
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<V::Value, E> {
+        self.0.visit_u64(v)
+    }
+
+    fn visit_i128<E: de::Error>(self, v: i128) -> Result<V::Value, E> {
+        self.0.visit_i128(v)
+    }
+
+    fn visit_u128<E: de::Error>(self, v: u128) -> Result<V::Value, E> {
+        self.0.visit_u128(v)
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<V::Value, E> {
+        self.0.visit_f64(v)
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<V::Value, E> {
+        self.0.visit_str(v)
+    }
+
+    fn visit_borrowed_str<E: de::Error>(self, v: &'de str) -> Result<V::Value, E> {
+        self.0.visit_borrowed_str(v)
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<V::Value, E> {
+        self.0.visit_string(v)
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<V::Value, E> {
+        self.0.visit_bytes(v)
+    }
+
+    fn visit_borrowed_bytes<E: de::Error>(self, v: &'de [u8]) -> Result<V::Value, E> {
+        self.0.visit_borrowed_bytes(v)
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<V::Value, E> {
+        self.0.visit_byte_buf(v)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<V::Value, E> {
+        self.0.visit_none()
+    }
+
+    fn visit_some<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<V::Value, D::Error> {
+        self.0.visit_some(Int64Deserializer(deserializer))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<V::Value, E> {
+        self.0.visit_unit()
+    }
+
+    fn visit_newtype_struct<D: de::Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<V::Value, D::Error> {
+        self.0.visit_newtype_struct(Int64Deserializer(deserializer))
+    }
+
+    fn visit_seq<A: de::SeqAccess<'de>>(self, seq: A) -> Result<V::Value, A::Error> {
+        self.0.visit_seq(Wrap(seq))
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, map: A) -> Result<V::Value, A::Error> {
+        self.0.visit_map(Wrap(map))
+    }
+
+    fn visit_enum<A: de::EnumAccess<'de>>(self, data: A) -> Result<V::Value, A::Error> {
+        self.0.visit_enum(Wrap(data))
+    }
+}
+
+impl<'de, T: DeserializeSeed<'de>> DeserializeSeed<'de> for Wrap<T> {
+    type Value = T::Value;
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<T::Value, D::Error> {
+        self.0.deserialize(Int64Deserializer(deserializer))
+    }
+}
+
+impl<'de, A: de::SeqAccess<'de>> de::SeqAccess<'de> for Wrap<A> {
+    type Error = A::Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, A::Error> {
+        self.0.next_element_seed(Wrap(seed))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.0.size_hint()
+    }
+}
+
+impl<'de, A: de::MapAccess<'de>> de::MapAccess<'de> for Wrap<A> {
+    type Error = A::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, A::Error> {
+        self.0.next_key_seed(seed)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, A::Error> {
+        self.0.next_value_seed(Wrap(seed))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.0.size_hint()
+    }
+}
+
+impl<'de, A: de::EnumAccess<'de>> de::EnumAccess<'de> for Wrap<A> {
+    type Error = A::Error;
+    type Variant = Wrap<A::Variant>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self::Variant), A::Error> {
+        let (value, variant) = self.0.variant_seed(seed)?;
+        Ok((value, Wrap(variant)))
+    }
+}
+
+impl<'de, A: de::VariantAccess<'de>> de::VariantAccess<'de> for Wrap<A> {
+    type Error = A::Error;
+
+    fn unit_variant(self) -> Result<(), A::Error> {
+        self.0.unit_variant()
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, A::Error> {
+        self.0.newtype_variant_seed(Wrap(seed))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, A::Error> {
+        self.0.tuple_variant(len, Wrap(visitor))
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, A::Error> {
+        self.0.struct_variant(fields, Wrap(visitor))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::error::ErrorResponse;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Account {
+        id: u64,
+        balance: i64,
+        small: u32,
+        limit: Option<u64>,
+        history: Vec<i64>,
+        owner: Owner,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Owner {
+        name: String,
+        ids: BTreeMap<String, u64>,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    enum Limit {
+        Unlimited,
+        Fixed(u64),
+        Range { min: i64, max: i64 },
+    }
+
+    fn account() -> Account {
+        Account {
+            id: u64::MAX,
+            balance: i64::MIN,
+            small: 7,
+            limit: Some(1 << 53),
+            history: vec![i64::MAX, -1],
+            owner: Owner {
+                name: "ada".to_owned(),
+                ids: BTreeMap::from([("a".to_owned(), 1), ("b".to_owned(), 2)]),
+            },
+        }
+    }
+
+    #[test]
+    fn writes_integer_edges_as_strings() {
+        assert_eq!(to_string(&u64::MAX).unwrap(), r#""18446744073709551615""#);
+        assert_eq!(to_string(&i64::MIN).unwrap(), r#""-9223372036854775808""#);
+        assert_eq!(
+            to_string(&u128::MAX).unwrap(),
+            r#""340282366920938463463374607431768211455""#
+        );
+        assert_eq!(to_string(&(7u32, 1.5f64)).unwrap(), "[7,1.5]");
+    }
+
+    #[test]
+    fn round_trips_nested_values() {
+        let json = to_string(&account()).unwrap();
+        assert_eq!(
+            json,
+            r#"{"id":"18446744073709551615","balance":"-9223372036854775808","small":7,"limit":"9007199254740992","history":["9223372036854775807","-1"],"owner":{"name":"ada","ids":{"a":"1","b":"2"}}}"#
+        );
+        assert_eq!(from_str::<Account>(&json).unwrap(), account());
+
+        let none = Account {
+            limit: None,
+            ..account()
+        };
+        assert_eq!(
+            from_str::<Account>(&to_string(&none).unwrap()).unwrap(),
+            none
+        );
+    }
+
+    #[test]
+    fn records_the_paths_of_integers() {
+        let (_, paths) = to_string_with_paths(&account()).unwrap();
+        assert_eq!(
+            paths,
+            r#"[["id"],["balance"],["limit"],["history",0],["history",1],["owner","ids","a"],["owner","ids","b"]]"#
+        );
+
+        let (_, paths) = to_string_with_paths(&5u64).unwrap();
+        assert_eq!(paths, "[[]]");
+
+        let (json, paths) = to_string_with_paths(&vec![
+            Limit::Unlimited,
+            Limit::Fixed(3),
+            Limit::Range { min: -1, max: 1 },
+        ])
+        .unwrap();
+        assert_eq!(
+            json,
+            r#"["unlimited",{"fixed":"3"},{"range":{"min":"-1","max":"1"}}]"#
+        );
+        assert_eq!(
+            paths,
+            r#"[[1,"fixed"],[2,"range","min"],[2,"range","max"]]"#
+        );
+        assert_eq!(from_str::<Vec<Limit>>(&json).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn reads_numbers_and_strings() {
+        assert_eq!(from_str::<u64>("18446744073709551615").unwrap(), u64::MAX);
+        assert_eq!(
+            from_str::<u64>(r#""18446744073709551615""#).unwrap(),
+            u64::MAX
+        );
+        assert_eq!(from_str::<i64>("-9223372036854775808").unwrap(), i64::MIN);
+        assert_eq!(
+            from_str::<i64>(r#""-9223372036854775808""#).unwrap(),
+            i64::MIN
+        );
+        assert_eq!(
+            from_str::<Vec<Option<u64>>>(r#"[1, "2", null]"#).unwrap(),
+            vec![Some(1), Some(2), None]
+        );
+
+        assert!(from_str::<u64>(r#""-1""#).is_err());
+        assert!(from_str::<u64>(r#""18446744073709551616""#).is_err());
+        assert!(from_str::<i64>(r#""one""#).is_err());
+        assert!(from_str::<u32>(r#""1""#).is_err());
+    }
+
+    #[test]
+    fn reads_128_bit_numbers_exactly() {
+        assert_eq!(
+            from_str::<u128>("340282366920938463463374607431768211455").unwrap(),
+            u128::MAX
+        );
+        assert_eq!(
+            from_str::<u128>(r#""340282366920938463463374607431768211455""#).unwrap(),
+            u128::MAX
+        );
+        assert_eq!(
+            from_str::<i128>("-170141183460469231731687303715884105728").unwrap(),
+            i128::MIN
+        );
+        assert_eq!(
+            from_str::<Vec<i128>>(r#"[ -1 , "2" ]"#).unwrap(),
+            vec![-1, 2]
+        );
+
+        assert!(from_str::<u128>("-1").is_err());
+        assert!(from_str::<u128>("1.5").is_err());
+        assert!(from_str::<i128>("null").is_err());
+    }
+
+    #[test]
+    fn carries_paths_in_error_details() {
+        let (details, paths) = to_string_with_paths(&Limit::Fixed(u64::MAX)).unwrap();
+        let response = ErrorResponse::service_error("limits::set", &details, Some(&paths));
+
+        assert_eq!(
+            response.to_json(),
+            r#"{"error":{"message":"Service error","code":"service_error","details":{"fixed":"18446744073709551615"},"service":"limits::set","bigints":[["fixed"]]}}"#
+        );
+    }
+}
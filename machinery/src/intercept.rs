@@ -0,0 +1,47 @@
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::{context::Context, MachineryResponse};
+
+/// A service call as interceptors see it.
+#[derive(Debug)]
+pub struct Call {
+    /// The name the client calls the service by, eg. `api::greeting::format`.
+    pub service: String,
+    /// The JSON array of arguments, `Null` when the input isn't JSON.
+    pub args: Value,
+}
+
+/// Runs around every service call of a `Machinery`, an error from `before`
+/// answers the call without running the service and an error from `after`
+/// replaces its response.
+///
+/// Interceptors of a single service are async functions given to
+/// `#[machinery::service(before = check_quota, after = audit)]`, with the
+/// signatures of `before` and `after` minus `&self`.
+#[async_trait::async_trait]
+pub trait Interceptor: Send + Sync {
+    async fn before(&self, _ctx: &Context, _call: &Call) -> Result<()> {
+        Ok(())
+    }
+
+    async fn after(
+        &self,
+        _ctx: &Context,
+        _call: &Call,
+        _response: &MachineryResponse,
+    ) -> Result<()> {
+        Ok(())
+    }
+}
+
+pub mod __internal {
+    use super::Call;
+
+    pub fn call(service: &str, json_input: &str) -> Call {
+        Call {
+            service: service.to_owned(),
+            args: serde_json::from_str(json_input).unwrap_or_default(),
+        }
+    }
+}
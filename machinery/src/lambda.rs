@@ -0,0 +1,453 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+};
+
+use anyhow::{bail, Context as _, Result};
+use axum::{
+    body::Body,
+    extract::ConnectInfo,
+    http::{Method, Request, Response},
+    Router,
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use hyper::{body::HttpBody, Client};
+use serde::{Deserialize, Serialize};
+use tower::ServiceExt;
+
+const RUNTIME_API_VERSION: &str = "2018-06-01";
+
+/// An API Gateway proxy event, REST (v1) or HTTP API and Function URL (v2).
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HttpEvent {
+    // v1
+    http_method: Option<String>,
+    path: Option<String>,
+    query_string_parameters: Option<HashMap<String, String>>,
+    multi_value_query_string_parameters: Option<HashMap<String, Vec<String>>>,
+    // v2
+    raw_path: Option<String>,
+    raw_query_string: Option<String>,
+    cookies: Option<Vec<String>>,
+
+    headers: Option<HashMap<String, String>>,
+    body: Option<String>,
+    #[serde(default)]
+    is_base64_encoded: bool,
+    #[serde(default)]
+    request_context: RequestContext,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RequestContext {
+    request_id: Option<String>,
+    // v1
+    identity: Option<Identity>,
+    // v2
+    http: Option<HttpContext>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Identity {
+    source_ip: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HttpContext {
+    method: String,
+    source_ip: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct HttpResponse {
+    status_code: u16,
+    headers: HashMap<String, String>,
+    body: String,
+    is_base64_encoded: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct InvocationError {
+    error_message: String,
+    error_type: String,
+}
+
+impl HttpEvent {
+    fn into_request(self) -> Result<Request<Body>> {
+        let method = match (&self.request_context.http, &self.http_method) {
+            (Some(http), _) => http.method.clone(),
+            (None, Some(method)) => method.clone(),
+            (None, None) => bail!("Event has no HTTP method"),
+        };
+
+        let path = self
+            .raw_path
+            .or(self.path)
+            .unwrap_or_else(|| "/".to_owned());
+        // v1 parameters come decoded, the multi-value ones keep repeated keys
+        let query = match (
+            self.raw_query_string,
+            self.multi_value_query_string_parameters,
+            self.query_string_parameters,
+        ) {
+            (Some(query), _, _) => query,
+            (None, Some(parameters), _) => serde_urlencoded::to_string(
+                parameters
+                    .iter()
+                    .flat_map(|(key, values)| values.iter().map(move |value| (key, value)))
+                    .collect::<Vec<_>>(),
+            )?,
+            (None, None, Some(parameters)) => serde_urlencoded::to_string(parameters)?,
+            (None, None, None) => String::new(),
+        };
+        let uri = match query.is_empty() {
+            true => path,
+            false => format!("{}?{}", path, query),
+        };
+
+        let mut request = Request::builder()
+            .method(Method::from_bytes(method.as_bytes())?)
+            .uri(uri);
+        for (name, value) in self.headers.unwrap_or_default() {
+            request = request.header(name, value);
+        }
+        // v2 events move the cookies out of the headers
+        if let Some(cookies) = self.cookies {
+            request = request.header("cookie", cookies.join("; "));
+        }
+
+        let has_request_id = request
+            .headers_ref()
+            .is_some_and(|headers| headers.contains_key("x-request-id"));
+        if let (false, Some(request_id)) = (has_request_id, &self.request_context.request_id) {
+            request = request.header("x-request-id", request_id);
+        }
+
+        // API Gateway only tells the address of the client, not its port
+        let source_ip = match (&self.request_context.http, &self.request_context.identity) {
+            (Some(http), _) => http.source_ip.as_ref(),
+            (None, Some(identity)) => identity.source_ip.as_ref(),
+            (None, None) => None,
+        };
+        if let Some(ip) = source_ip.and_then(|ip| ip.parse::<IpAddr>().ok()) {
+            request = request.extension(ConnectInfo(SocketAddr::new(ip, 0)));
+        }
+
+        let body = match (self.body, self.is_base64_encoded) {
+            (Some(body), true) => BASE64.decode(body)?,
+            (Some(body), false) => body.into_bytes(),
+            (None, _) => Vec::new(),
+        };
+
+        Ok(request.body(Body::from(body))?)
+    }
+}
+
+async fn into_http_response<B>(response: Response<B>) -> Result<HttpResponse>
+where
+    B: HttpBody,
+    B::Error: std::error::Error + Send + Sync + 'static,
+{
+    let (parts, body) = response.into_parts();
+    let body = hyper::body::to_bytes(body).await?;
+
+    let headers = parts
+        .headers
+        .iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_owned())))
+        .collect();
+
+    let (body, is_base64_encoded) = match String::from_utf8(body.to_vec()) {
+        Ok(body) => (body, false),
+        Err(_) => (BASE64.encode(body), true),
+    };
+
+    Ok(HttpResponse {
+        status_code: parts.status.as_u16(),
+        headers,
+        body,
+        is_base64_encoded,
+    })
+}
+
+async fn handle_event(router: Router, event: &[u8]) -> Result<HttpResponse> {
+    let event: HttpEvent =
+        serde_json::from_slice(event).context("Event is not an API Gateway proxy event")?;
+    let response = router.oneshot(event.into_request()?).await?;
+
+    into_http_response(response).await
+}
+
+/// Serves the invocations of the Lambda runtime API at `runtime_api`, each
+/// event is turned into a request for `router`.
+pub(crate) async fn run(router: Router, runtime_api: &str) -> Result<()> {
+    let client = Client::new();
+    let base = format!("http://{}/{}/runtime", runtime_api, RUNTIME_API_VERSION);
+
+    loop {
+        let next = client
+            .get(format!("{}/invocation/next", base).parse()?)
+            .await
+            .context("Failed to get the next invocation")?;
+
+        let Some(request_id) = next
+            .headers()
+            .get("lambda-runtime-aws-request-id")
+            .and_then(|id| id.to_str().ok())
+            .map(|id| id.to_owned())
+        else {
+            bail!("Invocation without a request id");
+        };
+        let event = hyper::body::to_bytes(next.into_body()).await?;
+
+        let (path, body) = match handle_event(router.clone(), &event).await {
+            Ok(response) => ("response", serde_json::to_vec(&response)?),
+            Err(err) => {
+                log::error!("invocation {} failed: {:#}", request_id, err);
+                let error = InvocationError {
+                    error_message: format!("{:#}", err),
+                    error_type: "MachineryError".to_owned(),
+                };
+                ("error", serde_json::to_vec(&error)?)
+            }
+        };
+
+        let request = Request::post(format!("{}/invocation/{}/{}", base, request_id, path))
+            .header("content-type", "application/json")
+            .body(hyper::Body::from(body))?;
+        client
+            .request(request)
+            .await
+            .context("Failed to send the invocation result")?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use axum::{
+        extract::{Path, State},
+        http::{HeaderMap, StatusCode},
+        routing::{get, post},
+    };
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::{context::Context, Machinery, MachineryHandler, MachineryResponse};
+
+    // a local stand-in for the runtime API, it hands out the queued events and
+    // forwards whatever the function posts back
+    async fn runtime_api(
+        events: Vec<&'static str>,
+    ) -> (String, mpsc::UnboundedReceiver<(String, String)>) {
+        let events = Arc::new(Mutex::new(events.into_iter().rev().collect::<Vec<_>>()));
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        let app = Router::new()
+            .route(
+                "/2018-06-01/runtime/invocation/next",
+                get(
+                    |State((events, _)): State<(Arc<Mutex<Vec<&'static str>>>, _)>| async move {
+                        let Some(event) = events.lock().unwrap().pop() else {
+                            // keep the function waiting like the real API does
+                            std::future::pending::<()>().await;
+                            unreachable!();
+                        };
+                        let mut headers = HeaderMap::new();
+                        headers.insert(
+                            "lambda-runtime-aws-request-id",
+                            "request-1".parse().unwrap(),
+                        );
+                        (headers, event)
+                    },
+                ),
+            )
+            .route(
+                "/2018-06-01/runtime/invocation/:id/:kind",
+                post(
+                    |State((_, sender)): State<(_, mpsc::UnboundedSender<(String, String)>)>,
+                     Path((_, kind)): Path<(String, String)>,
+                     body: String| async move {
+                        sender.send((kind, body)).unwrap();
+                        StatusCode::ACCEPTED
+                    },
+                ),
+            )
+            .with_state((events, sender));
+
+        let server =
+            axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(app.into_make_service());
+        let addr = server.local_addr().to_string();
+        tokio::spawn(server);
+
+        (addr, receiver)
+    }
+
+    fn echo_handler(
+        ctx: Arc<Context>,
+        fn_name: String,
+        json_input: String,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = MachineryResponse> + Send>> {
+        Box::pin(async move {
+            let user = ctx
+                .headers
+                .get("user_id")
+                .map(|id| id.to_str().unwrap().to_owned());
+            MachineryResponse::ok(format!(
+                "{{ \"result\": [{:?}, {}, {:?}] }}",
+                fn_name, json_input, user
+            ))
+        })
+    }
+
+    fn context_handler(
+        ctx: Arc<Context>,
+        _fn_name: String,
+        _json_input: String,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = MachineryResponse> + Send>> {
+        Box::pin(async move {
+            let query: HashMap<String, String> = ctx.query().unwrap();
+            MachineryResponse::ok(format!(
+                "{} {} {:?} {} {:?}",
+                ctx.method, ctx.uri, ctx.remote_addr, ctx.request_id, query
+            ))
+        })
+    }
+
+    async fn invoke(handler: MachineryHandler, event: &'static str) -> (String, serde_json::Value) {
+        let (addr, mut results) = runtime_api(vec![event]).await;
+        let router = Machinery::new(handler).router();
+        let function = tokio::spawn(async move { run(router, &addr).await });
+
+        let (kind, body) = results.recv().await.unwrap();
+        function.abort();
+
+        (kind, serde_json::from_str(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn serves_function_url_events() {
+        let (kind, response) = invoke(
+            echo_handler,
+            r#"{
+                "version": "2.0",
+                "rawPath": "/x",
+                "rawQueryString": "",
+                "headers": { "x-machinery-service": "api::hello", "user_id": "7" },
+                "body": "WyJ3b3JsZCJd",
+                "isBase64Encoded": true,
+                "requestContext": { "http": { "method": "POST", "path": "/x" } }
+            }"#,
+        )
+        .await;
+
+        assert_eq!(kind, "response");
+        assert_eq!(response["statusCode"], 200);
+        assert_eq!(
+            response["body"],
+            r#"{ "result": ["api::hello", ["world"], Some("7")] }"#
+        );
+    }
+
+    #[tokio::test]
+    async fn serves_rest_api_events() {
+        let (kind, response) = invoke(
+            echo_handler,
+            r#"{
+                "httpMethod": "POST",
+                "path": "/x",
+                "headers": { "x-machinery-service": "api::hello" },
+                "queryStringParameters": null,
+                "body": "[1]",
+                "isBase64Encoded": false,
+                "requestContext": {}
+            }"#,
+        )
+        .await;
+
+        assert_eq!(kind, "response");
+        assert_eq!(response["statusCode"], 200);
+        assert_eq!(
+            response["body"],
+            r#"{ "result": ["api::hello", [1], None] }"#
+        );
+    }
+
+    #[tokio::test]
+    async fn keeps_error_statuses() {
+        let (kind, response) = invoke(
+            echo_handler,
+            r#"{
+                "httpMethod": "POST",
+                "path": "/x",
+                "headers": {},
+                "body": "[]"
+            }"#,
+        )
+        .await;
+
+        assert_eq!(kind, "response");
+        assert_eq!(response["statusCode"], 400);
+    }
+
+    #[tokio::test]
+    async fn reports_invalid_events() {
+        let (kind, response) =
+            invoke(echo_handler, r#"{ "detail-type": "Scheduled Event" }"#).await;
+
+        assert_eq!(kind, "error");
+        assert_eq!(response["errorType"], "MachineryError");
+    }
+
+    #[tokio::test]
+    async fn fills_the_context() {
+        let (_, response) = invoke(
+            context_handler,
+            r#"{
+                "rawPath": "/x",
+                "rawQueryString": "tenant=acme",
+                "headers": { "x-machinery-service": "api::hello" },
+                "body": "[]",
+                "requestContext": {
+                    "requestId": "abc-123",
+                    "http": { "method": "POST", "sourceIp": "203.0.113.7" }
+                }
+            }"#,
+        )
+        .await;
+
+        assert_eq!(
+            response["body"],
+            r#"POST /x?tenant=acme Some(203.0.113.7) abc-123 {"tenant": "acme"}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn encodes_rest_api_query_parameters() {
+        let (_, response) = invoke(
+            context_handler,
+            r#"{
+                "httpMethod": "POST",
+                "path": "/x",
+                "headers": { "x-machinery-service": "api::hello" },
+                "queryStringParameters": { "q": "a b&c" },
+                "multiValueQueryStringParameters": { "q": ["x", "a b&c"] },
+                "body": "[]",
+                "requestContext": { "requestId": "abc-123" }
+            }"#,
+        )
+        .await;
+
+        assert_eq!(
+            response["body"],
+            r#"POST /x?q=x&q=a+b%26c None abc-123 {"q": "a b&c"}"#
+        );
+    }
+}
@@ -1,7 +1,8 @@
+mod lambda;
 mod machinery;
 
 pub use machinery_meta::{
-    inject, inject_async, injectable, load_services, machinery, message, service,
+    guard, inject, inject_async, injectable, interceptor, load_services, machinery, message, service,
 };
 
 pub use serde::{Deserialize, Serialize};
@@ -10,10 +11,6 @@ pub use anyhow::Result;
 
 pub type Void = ();
 
-pub mod error {
-    pub use anyhow::{anyhow, bail, Context, Error};
-}
-
 pub mod __internal_async {
     pub use async_trait::async_trait;
 }
@@ -22,7 +19,11 @@ pub mod json {
     pub use serde_json::{from_str, to_string, to_string_pretty, Value};
 }
 
-pub use machinery::{Machinery, MachineryHandler, MachineryStandaloneConfig};
+pub use machinery::{Machinery, MachineryHandler, MachineryResponse, MachineryStandaloneConfig};
 
 pub mod context;
+pub mod error;
+pub mod guard;
 pub mod inject;
+pub mod int64;
+pub mod intercept;
@@ -1,24 +1,56 @@
 use anyhow::bail;
 use axum::{
-    http::{HeaderMap, StatusCode},
-    routing::post,
-    Router,
+    body::{Body, HttpBody},
+    http::{request::Parts, Request, StatusCode},
+    response::IntoResponse,
+    routing::{post, Route},
+    BoxError, RequestExt, Router,
 };
 use std::net::SocketAddr;
-use std::{future::Future, pin::Pin};
+use std::sync::Arc;
+use std::{convert::Infallible, future::Future, pin::Pin};
+use tower::{Layer, Service};
 
-use crate::context::Context;
+use crate::context::{AppState, Context};
+use crate::error::ErrorResponse;
+use crate::intercept::Interceptor;
 
-pub type MachineryHandler =
-    fn(Context, String, String) -> Pin<Box<dyn Future<Output = String> + Send + 'static>>;
+pub type MachineryHandler = fn(
+    Arc<Context>,
+    String,
+    String,
+)
+    -> Pin<Box<dyn Future<Output = MachineryResponse> + Send + 'static>>;
+
+/// The JSON body of a service call and the HTTP status it is answered with.
+#[derive(Debug)]
+pub struct MachineryResponse {
+    pub status: StatusCode,
+    pub body: String,
+}
+
+impl MachineryResponse {
+    pub fn ok(body: String) -> Self {
+        MachineryResponse {
+            status: StatusCode::OK,
+            body,
+        }
+    }
+}
 
 pub struct MachineryStandaloneConfig {
     pub listen_addr: SocketAddr,
 }
 
+type RouterLayer = Box<dyn Fn(Router) -> Router + Send + Sync>;
+
 pub struct Machinery {
     handler: MachineryHandler,
     standalone_config: Option<MachineryStandaloneConfig>,
+    layers: Vec<RouterLayer>,
+    interceptors: Vec<Arc<dyn Interceptor>>,
+    trusted_proxies: usize,
+    state: Arc<AppState>,
 }
 
 impl Machinery {
@@ -26,6 +58,10 @@ impl Machinery {
         Self {
             handler,
             standalone_config: None,
+            layers: Vec::new(),
+            interceptors: Vec::new(),
+            trusted_proxies: 0,
+            state: Arc::default(),
         }
     }
 
@@ -34,34 +70,117 @@ impl Machinery {
         self
     }
 
-    pub async fn boot(&self) -> anyhow::Result<()> {
+    /// Wraps the `/x` endpoint in a tower layer (timeouts, tracing, CORS...),
+    /// the layer added last runs first.
+    pub fn layer<L>(&mut self, layer: L) -> &mut Self
+    where
+        L: Layer<Route> + Clone + Send + Sync + 'static,
+        L::Service: Service<Request<Body>> + Clone + Send + 'static,
+        <L::Service as Service<Request<Body>>>::Response: IntoResponse + 'static,
+        <L::Service as Service<Request<Body>>>::Error: Into<Infallible> + 'static,
+        <L::Service as Service<Request<Body>>>::Future: Send + 'static,
+    {
+        self.layers
+            .push(Box::new(move |router: Router| router.layer(layer.clone())));
+        self
+    }
+
+    /// Runs `interceptor` around every service call, interceptors run in the
+    /// order they were added.
+    pub fn intercept(&mut self, interceptor: impl Interceptor + 'static) -> &mut Self {
+        self.interceptors.push(Arc::new(interceptor));
+        self
+    }
+
+    /// Takes the client address of `Context` from `x-forwarded-for`, skipping
+    /// the `proxies` trusted proxies in front of the app that append to it.
+    pub fn trust_forwarded_for(&mut self, proxies: usize) -> &mut Self {
+        self.trusted_proxies = proxies;
+        self
+    }
+
+    /// Makes `state` available to services and injectables through
+    /// `ctx.state::<T>()`, there is one value per type.
+    pub fn with_state<T: Send + Sync + 'static>(&mut self, state: T) -> &mut Self {
+        Arc::make_mut(&mut self.state).insert(state);
+        self
+    }
+
+    pub(crate) fn router(&self) -> Router {
         let handler = self.handler;
+        let interceptors: Arc<[_]> = self.interceptors.clone().into();
+        let trusted_proxies = self.trusted_proxies;
+        let state = self.state.clone();
 
-        let app = Router::new().route(
+        let router = Router::new().route(
             "/x",
-            post(move |headers: HeaderMap, body: String| async move {
-                let header = headers.get("x-machinery-service");
+            post(move |request: Request<Body>| async move {
+                let header = request.headers().get("x-machinery-service");
                 let Some(header) = header else {
-                    return (StatusCode::BAD_REQUEST, "{ \"error\": \"Missing service name\" }".to_owned());
+                    return (StatusCode::BAD_REQUEST, ErrorResponse::new("bad_request", "Missing service name").to_json());
                 };
                 let Ok(service) = header.to_str() else {
-                    return (StatusCode::BAD_REQUEST, "{ \"error\": \"Invalid service name\" }".to_owned());
+                    return (StatusCode::BAD_REQUEST, ErrorResponse::new("bad_request", "Invalid service name").to_json());
                 };
 
-                let ctx = Context {
-                    headers: headers.clone()
+                let service = service.to_owned();
+
+                let read = match request.with_limited_body() {
+                    Ok(request) => read_body(request).await,
+                    Err(request) => read_body(request).await,
+                };
+                let (parts, body) = match read {
+                    Ok(read) => read,
+                    Err(err) => return (err.status, err.to_json()),
                 };
 
-                let output = (handler)(ctx, service.to_owned(), body).await;
+                let ctx = Context::from_request(parts, trusted_proxies);
 
-                (StatusCode::OK, output)
+                let response = dispatch(handler, &interceptors, &state, ctx, service, body).await;
+
+                (response.status, response.body)
             }),
         );
 
-        if cfg!(feature = "lambda") {
+        self.layers
+            .iter()
+            .fold(router, |router, layer| layer(router))
+    }
+
+    /// Calls a service directly, `service` is the name the client sends, eg.
+    /// `api::greeting::format`, and `payload` the JSON array of its arguments.
+    pub async fn call(&self, ctx: Context, service: &str, payload: &str) -> MachineryResponse {
+        dispatch(
+            self.handler,
+            &self.interceptors,
+            &self.state,
+            ctx,
+            service.to_owned(),
+            payload.to_owned(),
+        )
+        .await
+    }
+
+    /// The `/x` endpoint as a router of its own, for apps that serve it next
+    /// to their own routes, eg. `app.nest_service("/rpc", machinery.into_router())`
+    /// answers at `/rpc/x`. `Context::remote_addr` needs the app to be served
+    /// with `into_make_service_with_connect_info::<SocketAddr>()`.
+    pub fn into_router(self) -> Router {
+        self.router()
+    }
+
+    pub async fn boot(&self) -> anyhow::Result<()> {
+        let app = self.router();
+
+        // the runtime API is always there when running inside Lambda
+        let runtime_api = std::env::var("AWS_LAMBDA_RUNTIME_API").ok();
+        if cfg!(feature = "lambda") || runtime_api.is_some() {
             log::info!("using lambda server");
+            let Some(runtime_api) = runtime_api else {
+                bail!("Machinery lambda feature is enabled, but AWS_LAMBDA_RUNTIME_API is not set.");
+            };
 
-            return Ok(());
+            return crate::lambda::run(app, &runtime_api).await;
         }
 
         if cfg!(feature = "standalone") {
@@ -73,7 +192,7 @@ impl Machinery {
             log::info!("listening on {}", standalone_config.listen_addr);
 
             axum::Server::bind(&standalone_config.listen_addr)
-                .serve(app.into_make_service())
+                .serve(app.into_make_service_with_connect_info::<SocketAddr>())
                 .await?;
 
             return Ok(());
@@ -82,3 +201,58 @@ impl Machinery {
         bail!("No machinery serving feature is enabled.");
     }
 }
+
+// reads the body within the `DefaultBodyLimit` of the router, like the
+// `String` extractor does
+async fn read_body<B>(request: Request<B>) -> Result<(Parts, String), ErrorResponse>
+where
+    B: HttpBody,
+    B::Error: Into<BoxError>,
+{
+    let (parts, body) = request.into_parts();
+    let body = hyper::body::to_bytes(body).await.map_err(|err| {
+        let err: BoxError = err.into();
+        if err.is::<http_body::LengthLimitError>() {
+            ErrorResponse::new("payload_too_large", "Body is too large")
+        } else {
+            ErrorResponse::new("bad_request", "Failed to read the body")
+        }
+    })?;
+    let Ok(body) = String::from_utf8(body.to_vec()) else {
+        return Err(ErrorResponse::new("bad_request", "Body is not valid UTF-8"));
+    };
+
+    Ok((parts, body))
+}
+
+async fn dispatch(
+    handler: MachineryHandler,
+    interceptors: &[Arc<dyn Interceptor>],
+    state: &Arc<AppState>,
+    mut ctx: Context,
+    service: String,
+    payload: String,
+) -> MachineryResponse {
+    ctx.set_state(state.clone());
+    let ctx = Arc::new(ctx);
+    if interceptors.is_empty() {
+        return (handler)(ctx, service, payload).await;
+    }
+
+    let call = crate::intercept::__internal::call(&service, &payload);
+    for interceptor in interceptors {
+        if let Err(err) = interceptor.before(&ctx, &call).await {
+            return ErrorResponse::from_error(err, "internal").into_response();
+        }
+    }
+
+    let response = (handler)(ctx.clone(), service, payload).await;
+
+    for interceptor in interceptors {
+        if let Err(err) = interceptor.after(&ctx, &call, &response).await {
+            return ErrorResponse::from_error(err, "internal").into_response();
+        }
+    }
+
+    response
+}